@@ -0,0 +1,85 @@
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::platforms::http::Mode;
+use crate::platforms::instagram::Tokens;
+
+/// Runtime settings, read from the environment once at startup.
+///
+/// - `MEDIASCRAPER_HTTP_MODE`: `live` (default), `record` or `replay`
+/// - `MEDIASCRAPER_CASSETTES`: cassette directory for record/replay, defaults to `cassettes`
+/// - `MEDIASCRAPER_INSTAGRAM_COOKIE`: optional logged-in cookie (`sessionid=...`) for stories
+/// - `MEDIASCRAPER_FACEBOOK_COOKIE`: optional logged-in cookie (`c_user=...; xs=...`) for stories
+/// - `MEDIASCRAPER_INSTAGRAM_DOC_ID`, `_LSD`, `_REV`, `_SPIN_T`, `_CSRF`, `_TIMELINE_HASH`,
+///   `_COMMENTS_HASH`: starting GraphQL tokens,
+///   used until fresh ones are bootstrapped from instagram.com and whenever that fails
+/// - `MEDIASCRAPER_INSTAGRAM_TOKEN_TTL`: seconds bootstrapped tokens are reused, defaults to 3600
+/// - `MEDIASCRAPER_MERGE_JOBS`: Facebook DASH merges run at once, defaults to 2; more are refused
+/// - `MEDIASCRAPER_FFMPEG`: ffmpeg binary for rendering slideshows, defaults to `ffmpeg` on `PATH`
+/// - `MEDIASCRAPER_RENDER_JOBS`: slideshows downloaded and rendered at once, defaults to 2; more are refused
+/// - `MEDIASCRAPER_RENDER_TIMEOUT`: seconds a render's downloads, and then ffmpeg, may each take, defaults to 120
+pub struct Config {
+    pub http_mode: Mode,
+    pub instagram_cookie: Option<String>,
+    pub facebook_cookie: Option<String>,
+    pub instagram_tokens: Tokens,
+    pub instagram_token_ttl: Duration,
+    pub merge_jobs: usize,
+    pub ffmpeg: PathBuf,
+    pub render_jobs: usize,
+    pub render_timeout: Duration,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let cassettes = PathBuf::from(env::var("MEDIASCRAPER_CASSETTES").unwrap_or_else(|_| "cassettes".to_string()));
+        let http_mode = match env::var("MEDIASCRAPER_HTTP_MODE").as_deref() {
+            Ok("record") => Mode::Record(cassettes),
+            Ok("replay") => Mode::Replay(cassettes),
+            _ => Mode::Live,
+        };
+        let instagram_cookie = env::var("MEDIASCRAPER_INSTAGRAM_COOKIE").ok().filter(|c| !c.is_empty());
+        let facebook_cookie = env::var("MEDIASCRAPER_FACEBOOK_COOKIE").ok().filter(|c| !c.is_empty());
+
+        let mut instagram_tokens = Tokens::default();
+        for (name, token) in [
+            ("MEDIASCRAPER_INSTAGRAM_DOC_ID", &mut instagram_tokens.doc_id),
+            ("MEDIASCRAPER_INSTAGRAM_LSD", &mut instagram_tokens.lsd),
+            ("MEDIASCRAPER_INSTAGRAM_REV", &mut instagram_tokens.rev),
+            ("MEDIASCRAPER_INSTAGRAM_SPIN_T", &mut instagram_tokens.spin_t),
+            ("MEDIASCRAPER_INSTAGRAM_CSRF", &mut instagram_tokens.csrf),
+            ("MEDIASCRAPER_INSTAGRAM_TIMELINE_HASH", &mut instagram_tokens.timeline_hash),
+            ("MEDIASCRAPER_INSTAGRAM_COMMENTS_HASH", &mut instagram_tokens.comments_hash),
+        ] {
+            if let Ok(value) = env::var(name)
+                && !value.is_empty()
+            {
+                *token = value;
+            }
+        }
+        let instagram_token_ttl = env::var("MEDIASCRAPER_INSTAGRAM_TOKEN_TTL").ok()
+            .and_then(|ttl| ttl.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(3600));
+
+        let merge_jobs = env::var("MEDIASCRAPER_MERGE_JOBS").ok()
+            .and_then(|jobs| jobs.parse().ok())
+            .unwrap_or(2);
+
+        let ffmpeg = env::var("MEDIASCRAPER_FFMPEG").ok()
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("ffmpeg"));
+
+        let render_jobs = env::var("MEDIASCRAPER_RENDER_JOBS").ok()
+            .and_then(|jobs| jobs.parse().ok())
+            .unwrap_or(2);
+        let render_timeout = env::var("MEDIASCRAPER_RENDER_TIMEOUT").ok()
+            .and_then(|timeout| timeout.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(120));
+
+        Self { http_mode, instagram_cookie, facebook_cookie, instagram_tokens, instagram_token_ttl, merge_jobs, ffmpeg, render_jobs, render_timeout }
+    }
+}
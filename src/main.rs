@@ -1,130 +1,114 @@
 use actix_web::{route, web, get, App, HttpResponse, HttpServer, Responder};
+use actix_web::http::StatusCode;
 use actix_files::Files;
 use tera::Tera;
-use regex::Regex;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 
+mod config;
+mod media;
 mod platforms;
-use platforms::{tiktok::TikTokv2, facebook::Facebook, instagram::Instagram};
+mod remux;
+mod slideshow;
+use config::Config;
+use platforms::{http::Http, Registry, ScrapeError};
 
 #[derive(Serialize)]
 struct ApiResponse<T> {
     success: bool,
     data: Option<T>,
     message: Option<String>,
+    error_code: Option<&'static str>,
     error_message: Option<String>,
 }
 
-struct Validator;
+impl ApiResponse<()> {
+    fn error(e: &ScrapeError) -> HttpResponse {
+        let code = StatusCode::from_u16(e.status()).unwrap_or(StatusCode::BAD_GATEWAY);
+        HttpResponse::build(code).json(ApiResponse::<()> {
+            success: false,
+            data: None,
+            message: Some(e.message().to_string()),
+            error_code: Some(e.code()),
+            error_message: Some(e.to_string()),
+        })
+    }
+}
+
+#[route("/api/", method = "GET", method = "POST")]
+async fn api_handler(http: web::Data<Http>, registry: web::Data<Registry>, body: web::Bytes, query: web::Query<HashMap<String, String>>,) -> impl Responder {
+    let json: HashMap<String, Value> = serde_json::from_slice(&body).unwrap_or_default();
+
+    let cut = json.contains_key("cut") || query.contains_key("cut");
+    let url = json.get("url").and_then(|v| v.as_str()).or_else(|| query.get("url").map(|s| s.as_str()));
 
-impl Validator {
-    fn validate(url: &str) -> (&'static str, Option<String>) {
-        let tiktok_pattern = Regex::new(r"tiktok\.com/.*/").unwrap();
-        let instag_pattern = Regex::new(r"instagram\.com/(p|reel|tv)/([A-Za-z0-9_-]+)/?").unwrap();
-        let facebook_pattern = Regex::new(r"(facebook\.com/.*/|fb\.watch/.*/)").unwrap();
+    let Some(url) = url else {
+        return ApiResponse::error(&ScrapeError::InvalidUrl("URL is required".to_string()));
+    };
 
-        if tiktok_pattern.is_match(url) {
-            return ("TikTok", Some(url.to_string()));
-        }
-        if facebook_pattern.is_match(url) {
-            return ("Facebook", Some(url.to_string()));
-        }
-        if let Some(cap) = instag_pattern.captures(url) {
-            return ("Instagram", Some(cap[2].to_string()));
-        }
+    let Some(mut platform) = registry.resolve(url, cut, http.get_ref().clone()) else {
+        return ApiResponse::error(&ScrapeError::Unsupported);
+    };
 
-        ("Invalid URL", None)
+    match platform.fetch().await {
+        Ok(data) => ok(data),
+        Err(e) => ApiResponse::error(&e),
     }
 }
 
+#[derive(Deserialize)]
+struct PageQuery {
+    url: String,
+    cursor: Option<String>,
+}
 
-#[route("/api/", method = "GET", method = "POST")]
-async fn api_handler(client: web::Data<reqwest::Client>, body: web::Bytes, query: web::Query<HashMap<String, String>>,) -> impl Responder {
-    let json: HashMap<String, Value> = serde_json::from_slice(&body).unwrap_or_default();
-    
-    let cut = json.get("cut").is_some() || query.get("cut").is_some();
-    let json: HashMap<String, Value> = serde_json::from_slice(&body).unwrap_or_default();
-    let url = json.get("url").and_then(|v| v.as_str()).or_else(|| query.get("url").map(|s| s.as_str()));
+fn ok<T: Serialize>(data: T) -> HttpResponse {
+    HttpResponse::Ok().json(ApiResponse { success: true, data: Some(data), message: None, error_code: None, error_message: None })
+}
 
+#[get("/api/profile")]
+async fn profile_handler(http: web::Data<Http>, registry: web::Data<Registry>, query: web::Query<PageQuery>) -> impl Responder {
+    let Some(mut platform) = registry.resolve(&query.url, true, http.get_ref().clone()) else {
+        return ApiResponse::error(&ScrapeError::Unsupported);
+    };
+    match platform.profile(query.cursor.clone()).await {
+        Ok(profile) => ok(profile),
+        Err(e) => ApiResponse::error(&e),
+    }
+}
 
-    if url.is_none() {
-        return HttpResponse::BadRequest().json(ApiResponse::<()>{
-            success: false,
-            data: None,
-            message: Some("URL is requmired".to_string()),
-            error_message: None,
-        });
+#[derive(Deserialize)]
+struct CommentsQuery {
+    url: String,
+    cursor: Option<String>,
+    /// Pages through this comment's replies instead of the post's comments.
+    comment_id: Option<String>,
+}
+
+#[get("/api/comments")]
+async fn comments_handler(http: web::Data<Http>, registry: web::Data<Registry>, query: web::Query<CommentsQuery>) -> impl Responder {
+    let Some(mut platform) = registry.resolve(&query.url, true, http.get_ref().clone()) else {
+        return ApiResponse::error(&ScrapeError::Unsupported);
+    };
+    let page = match &query.comment_id {
+        Some(id) => platform.replies(id.clone(), query.cursor.clone()).await,
+        None => platform.comments(query.cursor.clone()).await,
+    };
+    match page {
+        Ok(comments) => ok(comments),
+        Err(e) => ApiResponse::error(&e),
     }
+}
 
-    let url = url.unwrap();
-    let (source, item_id) = Validator::validate(url);
-
-    match source {
-        "TikTok" => {
-            let mut tiktok = TikTokv2::new(url, cut, client.get_ref().clone());
-            let (data, status) = tiktok.get_data().await;
-            if status == 200 {
-                HttpResponse::Ok().json(ApiResponse { success: true, data: Some(data), message: None, error_message: None })
-            } else {
-                let code = actix_web::http::StatusCode::from_u16(status as u16).unwrap();
-                HttpResponse::build(code)
-                    .json(ApiResponse { 
-                        success: false, 
-                        data: Some(data.clone()), 
-                        message: data.get("message").and_then(|v| v.as_str().map(|s| s.to_string())), 
-                        error_message: data.get("error_message").and_then(|v| v.as_str().map(|s| s.to_string())) 
-                    })
-            }
-        },
-        "Facebook" => {
-            let mut fb = Facebook::new(&url.replace("web.facebook","www.facebook"), cut, client.get_ref().clone());
-            let (data, status) = fb.get_data().await;
-            if status == 200 {
-                HttpResponse::Ok().json(ApiResponse { success: true, data: Some(data), message: None, error_message: None })
-            } else {
-                let code = actix_web::http::StatusCode::from_u16(status as u16).unwrap();
-                HttpResponse::build(code)
-                    .json(ApiResponse { 
-                        success: false, 
-                        data: Some(data.clone()), 
-                        message: data.get("message").and_then(|v| v.as_str().map(|s| s.to_string())), 
-                        error_message: data.get("error_message").and_then(|v| v.as_str().map(|s| s.to_string())) 
-                    })
-            }
-        },
-        "Instagram" => {
-            if let Some(id) = item_id {
-                let insta = Instagram::new(client.get_ref().clone(), id, cut);
-                let (data, status) = insta.get_data().await;
-                if status == 200 {
-                    HttpResponse::Ok().json(ApiResponse { success: true, data: Some(data), message: None, error_message: None })
-                } else {
-                    let code = actix_web::http::StatusCode::from_u16(status as u16).unwrap();
-                    HttpResponse::build(code)
-                        .json(ApiResponse { 
-                            success: false, 
-                            data: Some(data.clone()), 
-                            message: data.get("message").and_then(|v| v.as_str().map(|s| s.to_string())), 
-                            error_message: data.get("error_message").and_then(|v| v.as_str().map(|s| s.to_string())) 
-                        })
-                }
-            } else {
-                HttpResponse::BadRequest().json(ApiResponse::<()>{
-                    success: false,
-                    data: None,
-                    message: Some("Invalid Instagram video URL".to_string()),
-                    error_message: None,
-                })
-            }
-        },
-        _ => HttpResponse::BadRequest().json(ApiResponse::<()>{
-            success: false,
-            data: None,
-            message: Some("Unsupported URL".to_string()),
-            error_message: None,
-        })
+#[get("/api/music")]
+async fn music_handler(http: web::Data<Http>, registry: web::Data<Registry>, query: web::Query<PageQuery>) -> impl Responder {
+    let Some(mut platform) = registry.resolve(&query.url, true, http.get_ref().clone()) else {
+        return ApiResponse::error(&ScrapeError::Unsupported);
+    };
+    match platform.sound(query.cursor.clone()).await {
+        Ok(sound) => ok(sound),
+        Err(e) => ApiResponse::error(&e),
     }
 }
 
@@ -138,20 +122,147 @@ async fn home(tmpl: web::Data<Tera>) -> impl Responder {
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let client = reqwest::Client::new();
+    let config = Config::from_env();
+    platforms::instagram::configure(config.instagram_tokens.clone(), config.instagram_token_ttl);
+    media::configure(config.merge_jobs);
+    slideshow::configure(config.ffmpeg.clone(), config.render_jobs, config.render_timeout);
+    let mut http = Http::new(reqwest::Client::new(), config.http_mode);
+    if let Some(cookie) = &config.instagram_cookie {
+        http = http.with_cookie("instagram.com", cookie);
+    }
+    if let Some(cookie) = &config.facebook_cookie {
+        http = http.with_cookie("facebook.com", cookie);
+    }
+    let http = web::Data::new(http);
+    let registry = web::Data::new(Registry::default());
     let tera = Tera::new("website/*html").unwrap();
 
     HttpServer::new(move || {
-        let client = client.clone();
         App::new()
             .service(home)
             .service(api_handler)
+            .service(profile_handler)
+            .service(comments_handler)
+            .service(music_handler)
+            .service(media::proxy)
+            .service(media::download)
             .service(Files::new("/static", "website/static").show_files_listing())
             .app_data(web::Data::new(tera.clone()))
-            .app_data(web::Data::new(client))
+            .app_data(http.clone())
+            .app_data(registry.clone())
     })
     .bind(("127.0.0.1", 8080))?
     .run()
     .await
 }
 
+
+#[cfg(test)]
+fn replay() -> web::Data<Http> {
+    let cassettes = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/cassettes");
+    web::Data::new(Http::new(reqwest::Client::new(), platforms::http::Mode::Replay(cassettes)))
+}
+
+#[actix_web::test]
+async fn api_replay() {
+    use actix_web::test;
+    let app = test::init_service(App::new().service(api_handler).app_data(replay()).app_data(web::Data::new(Registry::default()))).await;
+    let expected: Value = serde_json::from_str(include_str!("../fixtures/tiktok/video.cut.json")).unwrap();
+
+    let req = test::TestRequest::get()
+        .uri("/api/?cut=1&url=https://www.tiktok.com/@devfemibadmus/video/7390912680883899654")
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["success"], true);
+    assert_eq!(body["data"], expected);
+
+    let req = test::TestRequest::post()
+        .uri("/api/")
+        .set_json(serde_json::json!({ "url": "https://web.facebook.com/reel/1187613462356498", "cut": true }))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["content"]["id"], "1187613462356498");
+}
+
+#[actix_web::test]
+async fn profile_replay() {
+    use actix_web::test;
+    let app = test::init_service(App::new().service(profile_handler).app_data(replay()).app_data(web::Data::new(Registry::default()))).await;
+
+    let req = test::TestRequest::get().uri("/api/profile?url=https://www.instagram.com/devfemibadmus/").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["author"]["username"], "devfemibadmus");
+    assert_eq!(body["data"]["posts"]["cursor"], "QVFCcursor1");
+    assert_eq!(body["data"]["posts"]["items"][0]["url"], "https://www.instagram.com/p/C01xYzAbCd/");
+
+    let req = test::TestRequest::get().uri("/api/profile?url=https://www.tiktok.com/@devfemibadmus").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["platform"], "tiktok");
+    assert_eq!(body["data"]["posts"]["items"][1]["url"], "https://www.tiktok.com/@devfemibadmus/photo/7401234567890123402");
+
+    let req = test::TestRequest::get().uri("/api/profile?url=https://www.facebook.com/reel/1187613462356498/").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+}
+
+#[actix_web::test]
+async fn comments_replay() {
+    use actix_web::test;
+    let app = test::init_service(App::new().service(comments_handler).app_data(replay()).app_data(web::Data::new(Registry::default()))).await;
+
+    let req = test::TestRequest::get().uri("/api/comments?url=https://www.facebook.com/reel/1187613462356498").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["items"][0]["replies"][0]["text"], "Lagos Island, top of the mall");
+    assert_eq!(body["data"]["cursor"], "MTo3Mzk6Y3Vyc29yMQ==");
+
+    let req = test::TestRequest::get()
+        .uri("/api/comments?url=https://www.facebook.com/reel/1187613462356498&cursor=MTo3Mzk6Y3Vyc29yMQ%3D%3D")
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["items"][0]["text"], "Saved this for later");
+    assert_eq!(body["data"]["has_more"], false);
+
+    let req = test::TestRequest::get()
+        .uri("/api/comments?url=https://www.tiktok.com/@devfemibadmus/video/7390912680883899654&comment_id=7391000000000000001")
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["items"][1]["text"], "works on photo posts too");
+
+    let req = test::TestRequest::get()
+        .uri("/api/comments?url=https://www.instagram.com/p/DHm7knuzl1D/&comment_id=1")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+}
+
+#[actix_web::test]
+async fn music_replay() {
+    use actix_web::test;
+    let app = test::init_service(App::new().service(music_handler).app_data(replay()).app_data(web::Data::new(Registry::default()))).await;
+
+    let req = test::TestRequest::get().uri("/api/music?url=https://www.tiktok.com/music/original-sound-7390912800000000000").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["music"]["id"], "7390912800000000000");
+    assert_eq!(body["data"]["uses"], 1284);
+    assert_eq!(body["data"]["posts"]["cursor"], "30");
+
+    let req = test::TestRequest::get().uri("/api/music?url=https://www.instagram.com/devfemibadmus/").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+}
+
+#[actix_web::test]
+async fn api_replay_errors() {
+    use actix_web::test;
+    let app = test::init_service(App::new().service(api_handler).app_data(replay()).app_data(web::Data::new(Registry::default()))).await;
+
+    let req = test::TestRequest::get().uri("/api/?url=https://www.tiktok.com/@nobody/video/1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error_code"], "not_found");
+
+    let req = test::TestRequest::get().uri("/api/?url=https://example.com/video").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+}
@@ -0,0 +1,496 @@
+use actix_web::body::SizedStream;
+use actix_web::http::header::{self, ContentDisposition, DispositionParam, DispositionType};
+use actix_web::http::StatusCode;
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use reqwest::header::{HeaderMap, HeaderValue, IF_RANGE, RANGE};
+use serde::Deserialize;
+use std::sync::OnceLock;
+use tokio::sync::Semaphore;
+
+use crate::platforms::model::{MediaItem, MediaKind, Post, Scraped};
+use crate::platforms::{http::Http, Registry, ScrapeError};
+use crate::{remux, slideshow, ApiResponse};
+
+/// Upstream response headers the browser needs for playback and seeking.
+const PASSTHROUGH: [&str; 6] = ["content-type", "content-range", "accept-ranges", "etag", "last-modified", "cache-control"];
+
+/// Redirects followed while proxying before the last response is taken as is.
+const MAX_REDIRECTS: usize = 5;
+
+/// Largest media file read into memory to be merged or rendered.
+const MAX_FETCH_BYTES: usize = 200 * 1024 * 1024;
+
+static MERGES: OnceLock<Semaphore> = OnceLock::new();
+
+/// Sets how many DASH merges may run at once; when never called, 2.
+pub fn configure(merge_jobs: usize) {
+    let _ = MERGES.set(Semaphore::new(merge_jobs.max(1)));
+}
+
+fn merges() -> &'static Semaphore {
+    MERGES.get_or_init(|| Semaphore::new(2))
+}
+
+#[derive(Deserialize)]
+struct ProxyQuery {
+    url: String,
+    /// Whose media headers to send when platforms share the URL's CDN host.
+    platform: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DownloadQuery {
+    url: String,
+    index: Option<usize>,
+    quality: Option<String>,
+    /// Present to get a photo slideshow rendered to one MP4 instead of a single item.
+    render: Option<String>,
+}
+
+/// Fetches a media URL with its platform's headers and streams it back, forwarding `Range`
+/// so video seeking works. Only hosts some registered platform serves media from are allowed,
+/// and redirects are followed by hand so every hop is held to the same rule. `platform` picks
+/// the headers when several platforms share the host.
+pub async fn stream(http: &Http, registry: &Registry, url: &str, platform: Option<&str>, req: &HttpRequest) -> Result<HttpResponse, ScrapeError> {
+    let mut ranges = HeaderMap::new();
+    for name in [RANGE, IF_RANGE] {
+        if let Some(value) = req.headers().get(name.as_str()).and_then(|v| HeaderValue::from_bytes(v.as_bytes()).ok()) {
+            ranges.insert(name, value);
+        }
+    }
+    let upstream = follow(http, registry, url, platform, &ranges).await?;
+    let status = upstream.status().as_u16();
+    if status != 416
+        && let Some(e) = ScrapeError::from_media_status(status)
+    {
+        return Err(e);
+    }
+    let content_type = upstream.headers().get("content-type").and_then(|v| v.to_str().ok()).unwrap_or("");
+    if status != 416 && !is_media(content_type) {
+        return Err(ScrapeError::Unsupported);
+    }
+
+    let mut resp = HttpResponse::build(StatusCode::from_u16(status).unwrap_or(StatusCode::OK));
+    for name in PASSTHROUGH {
+        if let Some(value) = upstream.headers().get(name).and_then(|v| v.to_str().ok()) {
+            resp.insert_header((name, value.to_string()));
+        }
+    }
+    Ok(match upstream.content_length() {
+        Some(len) => resp.body(SizedStream::new(len, upstream.bytes_stream())),
+        None => resp.streaming(upstream.bytes_stream()),
+    })
+}
+
+/// GETs `url` on the media client with its platform's headers plus `extra`, following
+/// redirects by hand so every hop is held to the allowed hosts.
+async fn follow(http: &Http, registry: &Registry, url: &str, platform: Option<&str>, extra: &HeaderMap) -> Result<reqwest::Response, ScrapeError> {
+    let mut target = reqwest::Url::parse(url).map_err(|_| ScrapeError::InvalidUrl(url.to_string()))?;
+    let mut hops = 0;
+    loop {
+        let mut headers = allowed(registry, &target, platform)?;
+        headers.extend(extra.clone());
+        let upstream = http.media_client().get(target.clone()).headers(headers).send().await?;
+        let location = upstream.headers().get(reqwest::header::LOCATION).and_then(|v| v.to_str().ok());
+        match location {
+            Some(location) if upstream.status().is_redirection() && hops < MAX_REDIRECTS => {
+                target = target.join(location).map_err(|_| ScrapeError::UpstreamChanged(format!("bad redirect to {}", location)))?;
+                hops += 1;
+            }
+            _ => return Ok(upstream),
+        }
+    }
+}
+
+/// The platform's media headers for `target`, if its host is allowed.
+fn allowed(registry: &Registry, target: &reqwest::Url, platform: Option<&str>) -> Result<HeaderMap, ScrapeError> {
+    if !matches!(target.scheme(), "http" | "https") {
+        return Err(ScrapeError::InvalidUrl(target.to_string()));
+    }
+    target.host_str()
+        .and_then(|host| registry.media_headers(host, platform))
+        .ok_or(ScrapeError::Unsupported)
+}
+
+/// Media files, plus the DASH and HLS manifests a live broadcast's `address` points at.
+fn is_media(content_type: &str) -> bool {
+    [
+        "image/",
+        "video/",
+        "audio/",
+        "application/octet-stream",
+        "binary/octet-stream",
+        "application/dash+xml",
+        "application/vnd.apple.mpegurl",
+        "application/x-mpegurl",
+    ]
+    .iter()
+    .any(|prefix| content_type.to_ascii_lowercase().starts_with(prefix))
+}
+
+/// Downloads a whole media file the way `stream` reaches it, for when it must be processed
+/// rather than streamed. Files over `MAX_FETCH_BYTES` are refused.
+async fn fetch_bytes(http: &Http, registry: &Registry, url: &str, platform: &str) -> Result<Vec<u8>, ScrapeError> {
+    let too_large = || ScrapeError::Unavailable(format!("media over {} MiB can't be processed", MAX_FETCH_BYTES / 1024 / 1024));
+    let mut upstream = follow(http, registry, url, Some(platform), &HeaderMap::new()).await?;
+    if let Some(e) = ScrapeError::from_media_status(upstream.status().as_u16()) {
+        return Err(e);
+    }
+    if upstream.content_length().is_some_and(|len| len > MAX_FETCH_BYTES as u64) {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = upstream.chunk().await? {
+        if body.len() + chunk.len() > MAX_FETCH_BYTES {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Fetches a silent video and its separate audio stream and muxes them into one MP4. Both are
+/// held in memory, so a merge slot is taken before either is fetched.
+pub async fn merge(http: &Http, registry: &Registry, platform: &str, video: &str, audio: &str) -> Result<HttpResponse, ScrapeError> {
+    let _slot = merges().try_acquire().map_err(|_| ScrapeError::Busy)?;
+    let (video, audio) = futures::try_join!(fetch_bytes(http, registry, video, platform), fetch_bytes(http, registry, audio, platform))?;
+    let merged = remux::merge(&video, &audio).map_err(|e| ScrapeError::UpstreamChanged(format!("remux failed: {}", e)))?;
+    Ok(HttpResponse::Ok().content_type("video/mp4").body(merged))
+}
+
+/// Renders every image of a slideshow post over its music into an MP4. A render slot is taken
+/// before anything is downloaded, and the downloads get the render timeout of their own.
+pub async fn render(http: &Http, registry: &Registry, post: &Post) -> Result<HttpResponse, ScrapeError> {
+    if post.media.is_empty() || post.media.iter().any(|m| m.kind != MediaKind::Image) {
+        return Err(ScrapeError::BadRequest("only photo slideshows can be rendered".into()));
+    }
+    let renderer = slideshow::renderer();
+    let job = renderer.start().map_err(render_error)?;
+    let fetch = async {
+        let slides = futures::future::try_join_all(post.media.iter().map(|item| async move {
+            let address = item.address.as_deref()
+                .ok_or_else(|| ScrapeError::UpstreamChanged("slide has no address".into()))?;
+            fetch_bytes(http, registry, address, &post.platform).await
+        })).await?;
+        let audio = match post.music.as_ref().and_then(|m| m.src.as_deref()) {
+            Some(src) => Some(fetch_bytes(http, registry, src, &post.platform).await?),
+            None => None,
+        };
+        Ok::<_, ScrapeError>((slides, audio))
+    };
+    let (slides, audio) = tokio::time::timeout(renderer.timeout(), fetch).await
+        .map_err(|_| ScrapeError::Timeout("downloading the slides took too long".into()))??;
+    let size = slideshow::canvas(post.media[0].width, post.media[0].height);
+    let video = job.render(&slides, audio.as_deref(), size).await.map_err(render_error)?;
+    Ok(HttpResponse::Ok().content_type("video/mp4").body(video))
+}
+
+fn render_error(e: std::io::Error) -> ScrapeError {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => ScrapeError::Unavailable("ffmpeg is not installed".into()),
+        std::io::ErrorKind::WouldBlock => ScrapeError::Busy,
+        std::io::ErrorKind::TimedOut => ScrapeError::Timeout("rendering took too long".into()),
+        _ => ScrapeError::UpstreamChanged(format!("render failed: {}", e)),
+    }
+}
+
+/// Scrapes `url` in cut mode, the same way `/api/` does.
+pub async fn resolve(http: &Http, registry: &Registry, url: &str) -> Result<Post, ScrapeError> {
+    let mut platform = registry.resolve(url, true, http.clone()).ok_or(ScrapeError::Unsupported)?;
+    match platform.fetch().await? {
+        Scraped::Cut(post) => Ok(*post),
+        Scraped::Full(_) => Err(ScrapeError::UpstreamChanged("expected cut data".to_string())),
+    }
+}
+
+/// Media item `index` and the address of the requested variant, either by name or by position.
+pub fn pick<'a>(post: &'a Post, index: usize, quality: Option<&str>) -> Result<(&'a MediaItem, String), ScrapeError> {
+    let item = post.media.get(index)
+        .ok_or_else(|| ScrapeError::BadRequest(format!("no media at index {}", index)))?;
+    let address = match quality {
+        None => item.address.clone(),
+        Some(q) => item.variants.iter()
+            .position(|v| v.name.as_deref() == Some(q))
+            .or_else(|| q.parse::<usize>().ok().filter(|i| *i < item.variants.len()))
+            .map(|i| item.variants[i].address.clone())
+            .map(Some)
+            .ok_or_else(|| ScrapeError::BadRequest(format!("no quality {} for media {}", q, index)))?,
+    };
+    let address = address.ok_or_else(|| ScrapeError::UpstreamChanged(format!("media {} has no address", index)))?;
+    Ok((item, address))
+}
+
+/// `<author>_<id>.<ext>`, using the post id plus the position when the post has several items.
+pub fn filename(post: &Post, index: usize, content_type: &str) -> String {
+    let item = post.media.get(index);
+    let id = match (post.media.len(), item.and_then(|m| m.id.as_deref())) {
+        (1, Some(id)) => id.to_string(),
+        _ => format!("{}_{}", post.content.id.as_deref().unwrap_or("media"), index + 1),
+    };
+    let ext = extension(content_type).unwrap_or(match item.map(|m| m.kind) {
+        Some(MediaKind::Image) => "jpg",
+        Some(MediaKind::Audio) => "mp3",
+        _ => "mp4",
+    });
+    named(post, &id, ext)
+}
+
+/// `<author>_<id>.<ext>` with anything unsafe in a filename replaced.
+fn named(post: &Post, id: &str, ext: &str) -> String {
+    let author = post.author.username.as_deref()
+        .or(post.author.name.as_deref())
+        .unwrap_or(&post.platform);
+    let clean = |s: &str| -> String {
+        s.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' }).collect()
+    };
+    format!("{}_{}.{}", clean(author), clean(id), ext)
+}
+
+fn extension(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    Some(match mime {
+        "video/mp4" => "mp4",
+        "video/webm" => "webm",
+        "video/quicktime" => "mov",
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/webp" => "webp",
+        "image/heic" => "heic",
+        "audio/mpeg" => "mp3",
+        "audio/mp4" => "m4a",
+        _ => return None,
+    })
+}
+
+#[get("/api/download")]
+pub async fn download(http: web::Data<Http>, registry: web::Data<Registry>, query: web::Query<DownloadQuery>, req: HttpRequest) -> impl Responder {
+    let index = query.index.unwrap_or(0);
+    let result = async {
+        let post = resolve(&http, &registry, &query.url).await?;
+        let (mut resp, name) = if query.render.is_some() {
+            let resp = render(&http, &registry, &post).await?;
+            (resp, named(&post, post.content.id.as_deref().unwrap_or("media"), "mp4"))
+        } else {
+            let (item, address) = pick(&post, index, query.quality.as_deref())?;
+            if item.live.as_ref().is_some_and(|l| l.ongoing) {
+                return Err(ScrapeError::Unavailable("a broadcast still on air has no file to download, play its manifest instead".into()));
+            }
+            let silent = item.variants.iter()
+                .find(|v| v.address == address)
+                .and_then(|v| v.mime_type.as_deref())
+                .is_some_and(|m| m.starts_with("video/"));
+            let resp = match &item.audio {
+                Some(audio) if silent => merge(&http, &registry, &post.platform, &address, audio).await?,
+                _ => stream(&http, &registry, &address, Some(&post.platform), &req).await?,
+            };
+            let content_type = resp.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("");
+            let name = filename(&post, index, content_type);
+            (resp, name)
+        };
+        let disposition = ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(name)],
+        };
+        resp.headers_mut().insert(header::CONTENT_DISPOSITION, disposition.to_string().parse().unwrap());
+        Ok::<_, ScrapeError>(resp)
+    };
+    match result.await {
+        Ok(resp) => resp,
+        Err(e) => ApiResponse::error(&e),
+    }
+}
+
+#[get("/proxy")]
+pub async fn proxy(http: web::Data<Http>, registry: web::Data<Registry>, query: web::Query<ProxyQuery>, req: HttpRequest) -> impl Responder {
+    match stream(&http, &registry, &query.url, query.platform.as_deref(), &req).await {
+        Ok(resp) => resp,
+        Err(e) => ApiResponse::error(&e),
+    }
+}
+
+#[actix_web::test]
+async fn proxy_rejects_unknown_hosts() {
+    use actix_web::{test, App};
+    let http = Http::new(reqwest::Client::new(), crate::platforms::http::Mode::Live);
+    let app = test::init_service(
+        App::new()
+            .service(proxy)
+            .app_data(web::Data::new(http))
+            .app_data(web::Data::new(Registry::default())),
+    ).await;
+
+    for url in ["https%3A%2F%2Fexample.com%2Fa.mp4", "https%3A%2F%2Fevilfbcdn.net%2Fa.mp4", "file%3A%2F%2F%2Fetc%2Fpasswd", "not-a-url"] {
+        let req = test::TestRequest::get().uri(&format!("/proxy?url={}", url)).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400, "{}", url);
+    }
+}
+
+#[test]
+fn shared_cdn_headers_follow_the_platform() {
+    let registry = Registry::default();
+    let target = reqwest::Url::parse("https://scontent.xx.fbcdn.net/v/a.jpg").unwrap();
+    let referer = |platform| allowed(&registry, &target, platform).unwrap().get("referer").cloned();
+    assert_eq!(referer(Some("instagram")).unwrap(), "https://www.instagram.com");
+    assert_eq!(referer(Some("facebook")), None);
+    assert_eq!(referer(None), None);
+}
+
+#[actix_web::test]
+async fn proxy_checks_every_redirect() {
+    use actix_web::{test, App};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Serves a TikTok CDN host that redirects to another TikTok host, and that one to an
+    // internal address the proxy must never reach.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let hits = Arc::new(Mutex::new(Vec::<String>::new()));
+    let seen = hits.clone();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut buf = [0u8; 2048];
+            let n = socket.read(&mut buf).await.unwrap_or(0);
+            let path = String::from_utf8_lossy(&buf[..n]).split(' ').nth(1).unwrap_or("").to_string();
+            let location = match path.as_str() {
+                "/a.mp4" => format!("http://v16-webapp.tiktok.com:{}/b.mp4", addr.port()),
+                _ => format!("http://127.0.0.1:{}/internal", addr.port()),
+            };
+            seen.lock().unwrap().push(path);
+            let reply = format!("HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", location);
+            let _ = socket.write_all(reply.as_bytes()).await;
+        }
+    });
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve("v16-webapp.tiktok.com", addr)
+        .build()
+        .unwrap();
+    let http = Http::new(reqwest::Client::new(), crate::platforms::http::Mode::Live).with_media_client(client);
+    let app = test::init_service(
+        App::new()
+            .service(proxy)
+            .app_data(web::Data::new(http))
+            .app_data(web::Data::new(Registry::default())),
+    ).await;
+    let url = format!("http://v16-webapp.tiktok.com:{}/a.mp4", addr.port());
+    let req = test::TestRequest::get().uri(&format!("/proxy?url={}", crate::platforms::http::encode(&url))).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    assert_eq!(*hits.lock().unwrap(), ["/a.mp4", "/b.mp4"]);
+}
+
+#[actix_web::test]
+async fn proxy_passes_live_manifests() {
+    use actix_web::{test, App};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // A Facebook CDN host serving a live DASH manifest.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut buf = [0u8; 2048];
+            let _ = socket.read(&mut buf).await;
+            let mpd = "<MPD type=\"dynamic\"></MPD>";
+            let reply = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/dash+xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                mpd.len(),
+                mpd
+            );
+            let _ = socket.write_all(reply.as_bytes()).await;
+        }
+    });
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve("video.xx.fbcdn.net", addr)
+        .build()
+        .unwrap();
+    let http = Http::new(reqwest::Client::new(), crate::platforms::http::Mode::Live).with_media_client(client);
+    let app = test::init_service(
+        App::new()
+            .service(proxy)
+            .app_data(web::Data::new(http))
+            .app_data(web::Data::new(Registry::default())),
+    ).await;
+    let url = format!("http://video.xx.fbcdn.net:{}/live/1.mpd", addr.port());
+    let req = test::TestRequest::get().uri(&format!("/proxy?url={}", crate::platforms::http::encode(&url))).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers().get("content-type").unwrap(), "application/dash+xml");
+    assert!(is_media("application/vnd.apple.mpegurl"));
+    assert!(is_media("application/x-mpegURL"));
+}
+
+#[tokio::test]
+async fn fetch_checks_redirects_and_size() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // `/a.jpg` redirects to an allowed host that claims a file too big to hold, `/c.jpg` to an
+    // internal address.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut buf = [0u8; 2048];
+            let n = socket.read(&mut buf).await.unwrap_or(0);
+            let path = String::from_utf8_lossy(&buf[..n]).split(' ').nth(1).unwrap_or("").to_string();
+            let reply = match path.as_str() {
+                "/a.jpg" => format!("HTTP/1.1 302 Found\r\nLocation: http://v16-webapp.tiktok.com:{}/b.jpg\r\nContent-Length: 0\r\n", addr.port()),
+                "/b.jpg" => format!("HTTP/1.1 200 OK\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n", MAX_FETCH_BYTES + 1),
+                _ => format!("HTTP/1.1 302 Found\r\nLocation: http://127.0.0.1:{}/internal\r\nContent-Length: 0\r\n", addr.port()),
+            };
+            let _ = socket.write_all(format!("{}Connection: close\r\n\r\n", reply).as_bytes()).await;
+        }
+    });
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve("v16-webapp.tiktok.com", addr)
+        .build()
+        .unwrap();
+    let http = Http::new(reqwest::Client::new(), crate::platforms::http::Mode::Live).with_media_client(client);
+    let registry = Registry::default();
+    let url = |path: &str| format!("http://v16-webapp.tiktok.com:{}{}", addr.port(), path);
+    let err = fetch_bytes(&http, &registry, &url("/a.jpg"), "tiktok").await.unwrap_err();
+    assert_eq!(err.code(), "unavailable");
+    let err = fetch_bytes(&http, &registry, &url("/c.jpg"), "tiktok").await.unwrap_err();
+    assert_eq!(err.code(), "unsupported");
+}
+
+#[test]
+fn download_picks_media_and_names_file() {
+    let post: Post = serde_json::from_str(include_str!("../fixtures/tiktok/video.cut.json")).unwrap();
+    let (_, address) = pick(&post, 0, Some("normal_720_0")).unwrap();
+    assert_eq!(address, post.media[0].variants[1].address);
+    let (_, address) = pick(&post, 0, Some("2")).unwrap();
+    assert_eq!(address, post.media[0].variants[2].address);
+    assert!(pick(&post, 3, None).is_err());
+    assert!(pick(&post, 0, Some("normal_9000_0")).is_err());
+    assert_eq!(filename(&post, 0, "video/mp4"), "devfemibadmus_7390912680883899654.mp4");
+
+    let post: Post = serde_json::from_str(include_str!("../fixtures/instagram/sidecar.cut.json")).unwrap();
+    assert_eq!(filename(&post, 1, "video/mp4; charset=binary"), "devfemibadmus_3401234567890000000_2.mp4");
+    assert_eq!(filename(&post, 0, ""), "devfemibadmus_3401234567890000000_1.jpg");
+}
+
+#[tokio::test]
+async fn merge_is_capped_before_fetching() {
+    // No media client can reach this host, so only the cap can answer.
+    let http = Http::new(reqwest::Client::new(), crate::platforms::http::Mode::Live);
+    let _all = merges().acquire_many(merges().available_permits() as u32).await.unwrap();
+    let url = "https://video.xx.fbcdn.net/unreachable.mp4";
+    let err = merge(&http, &Registry::default(), "facebook", url, url).await.unwrap_err();
+    assert_eq!(err.code(), "busy");
+}
+
+#[tokio::test]
+async fn render_needs_a_slideshow() {
+    let http = Http::new(reqwest::Client::new(), crate::platforms::http::Mode::Live);
+    let post: Post = serde_json::from_str(include_str!("../fixtures/tiktok/video.cut.json")).unwrap();
+    let err = render(&http, &Registry::default(), &post).await.unwrap_err();
+    assert_eq!(err.code(), "bad_request");
+}
@@ -0,0 +1,144 @@
+use std::fmt;
+
+/// Every way a scrape can fail. `code()` is part of the public API and must not change.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScrapeError {
+    /// The request itself is unusable, e.g. no `url` was given.
+    InvalidUrl(String),
+    /// A parameter other than the URL is wrong, e.g. a media index past the end.
+    BadRequest(String),
+    /// No registered platform recognises the URL.
+    Unsupported,
+    /// The post was deleted or never existed.
+    NotFound,
+    /// A media link we were asked to fetch is refused or gone, usually a signed CDN URL that
+    /// expired. Scraping the post again gives fresh links.
+    Expired,
+    /// The post exists but only its owner's audience can see it.
+    Private,
+    /// The platform refuses to serve the content without a session.
+    LoginRequired,
+    /// The platform is throttling us.
+    RateLimited,
+    /// This server is already doing as much of the requested work as it allows, e.g. renders.
+    Busy,
+    /// The platform answered, but not in a shape we know how to read.
+    UpstreamChanged(String),
+    /// We could not reach the platform at all.
+    NetworkError(String),
+    /// The request is valid but this server lacks what it takes, e.g. ffmpeg for rendering.
+    Unavailable(String),
+    /// Work on our side, such as rendering, ran past its time limit.
+    Timeout(String),
+}
+
+impl ScrapeError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidUrl(_) => "invalid_url",
+            Self::BadRequest(_) => "bad_request",
+            Self::Unsupported => "unsupported",
+            Self::NotFound => "not_found",
+            Self::Expired => "expired",
+            Self::Private => "private",
+            Self::LoginRequired => "login_required",
+            Self::RateLimited => "rate_limited",
+            Self::Busy => "busy",
+            Self::UpstreamChanged(_) => "upstream_changed",
+            Self::NetworkError(_) => "network_error",
+            Self::Unavailable(_) => "unavailable",
+            Self::Timeout(_) => "timeout",
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::InvalidUrl(_) | Self::BadRequest(_) | Self::Unsupported => 400,
+            Self::LoginRequired => 401,
+            Self::Private => 403,
+            Self::NotFound => 404,
+            Self::Expired => 410,
+            Self::RateLimited => 429,
+            Self::UpstreamChanged(_) => 502,
+            Self::Unavailable(_) => 501,
+            Self::Busy => 503,
+            Self::NetworkError(_) | Self::Timeout(_) => 504,
+        }
+    }
+
+    /// Short, user-facing explanation.
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::InvalidUrl(_) => "Invalid URL",
+            Self::BadRequest(_) => "Invalid request",
+            Self::Unsupported => "Unsupported URL",
+            Self::NotFound => "Post not found or has been deleted",
+            Self::Expired => "Media link expired, fetch the post again",
+            Self::Private => "Post is private",
+            Self::LoginRequired => "Login required to view this post",
+            Self::RateLimited => "Too many requests, try again later",
+            Self::Busy => "Server is busy, try again later",
+            Self::UpstreamChanged(_) => "Unable to read the post, the platform may have changed",
+            Self::NetworkError(_) => "Unable to reach the platform",
+            Self::Unavailable(_) => "Not available on this server",
+            Self::Timeout(_) => "Took too long, try again later",
+        }
+    }
+
+    /// Maps an upstream HTTP status to an error, `None` when the status is a success.
+    pub fn from_status(status: u16) -> Option<Self> {
+        match status {
+            200..=299 => None,
+            401 => Some(Self::LoginRequired),
+            403 => Some(Self::Private),
+            404 | 410 => Some(Self::NotFound),
+            429 => Some(Self::RateLimited),
+            _ => Some(Self::UpstreamChanged(format!("unexpected status {}", status))),
+        }
+    }
+
+    /// Like [`from_status`](Self::from_status) but for a media CDN, which answers a stale
+    /// signed link with 403 or 410 whatever the post's visibility.
+    pub fn from_media_status(status: u16) -> Option<Self> {
+        match status {
+            401 | 403 | 404 | 410 => Some(Self::Expired),
+            _ => Self::from_status(status),
+        }
+    }
+}
+
+impl fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidUrl(detail) | Self::BadRequest(detail) | Self::UpstreamChanged(detail) | Self::NetworkError(detail)
+            | Self::Unavailable(detail) | Self::Timeout(detail) => {
+                write!(f, "{}: {}", self.message(), detail)
+            }
+            _ => f.write_str(self.message()),
+        }
+    }
+}
+
+impl std::error::Error for ScrapeError {}
+
+impl From<reqwest::Error> for ScrapeError {
+    fn from(e: reqwest::Error) -> Self {
+        match e.status().and_then(|s| Self::from_status(s.as_u16())) {
+            Some(err) => err,
+            None if e.is_decode() => Self::UpstreamChanged(e.to_string()),
+            None => Self::NetworkError(e.to_string()),
+        }
+    }
+}
+
+#[test]
+fn error_codes() {
+    assert_eq!(ScrapeError::from_status(404), Some(ScrapeError::NotFound));
+    assert_eq!(ScrapeError::from_status(200), None);
+    assert_eq!(ScrapeError::from_media_status(403), Some(ScrapeError::Expired));
+    assert_eq!(ScrapeError::from_media_status(429), Some(ScrapeError::RateLimited));
+    assert_eq!(ScrapeError::RateLimited.status(), 429);
+    assert_eq!(ScrapeError::Busy.code(), "busy");
+    assert_eq!(ScrapeError::UpstreamChanged("x".into()).code(), "upstream_changed");
+    assert_eq!(ScrapeError::Timeout("x".into()).status(), 504);
+}
@@ -1,19 +1,27 @@
-use reqwest::Client;
 use scraper::{Html, Selector};
 use serde_json::{json, Value};
+use futures::future::BoxFuture;
+use regex::Regex;
+
+use super::{locate, Outcome, Platform, ScrapeError};
+use super::http::{Http, Response};
+use super::model::{boolean, number, string, Author, Comment, Content, Live, MediaItem, MediaKind, Page, Post, Reactions, Scraped, Variant};
 
 pub struct Facebook {
     url: String,
     cut: bool,
-    client: Client,
+    http: Http,
 }
 
+/// Player fields describing a broadcast, folded onto the root for `cut`.
+const LIVE_FIELDS: [&str; 4] = ["is_live_streaming", "broadcast_status", "dash_manifest_url", "hls_playlist_url"];
+
 impl Facebook {
-    pub fn new(url: &str, cut: bool, client: Client) -> Self {
+    pub fn new(url: &str, cut: bool, http: Http) -> Self {
         Self {
             url: url.to_string(),
             cut,
-            client,
+            http,
         }
     }
 
@@ -48,228 +56,824 @@ impl Facebook {
         headers
     }
 
-    async fn get(&self, url: &str) -> reqwest::Result<reqwest::Response> {
-        self.client.get(url).headers(Self::headers()).send().await
+    /// Stories are the only pages fetched with the session cookie, when one is configured.
+    async fn get(&self, url: &str) -> Result<Response, ScrapeError> {
+        let headers = match self.is_story() {
+            true => self.http.with_session(url, Self::headers()),
+            false => Self::headers(),
+        };
+        self.http.get(url, headers).await
     }
 
-    fn get_nested_value<'a>(data: &'a Value, key: &str) -> Option<&'a Value> {
-        match data {
-            Value::Object(map) => {
-                if let Some(v) = map.get(key) {
-                    return Some(v);
-                }
-                for v in map.values() {
-                    if let Some(res) = Self::get_nested_value(v, key) {
-                        return Some(res);
-                    }
-                }
-                None
-            }
-            Value::Array(arr) => {
-                for v in arr {
-                    if let Some(res) = Self::get_nested_value(v, key) {
-                        return Some(res);
-                    }
-                }
-                None
-            }
-            _ => None,
-        }
+    fn is_story(&self) -> bool {
+        self.url.contains("facebook.com/stories/")
     }
 
-    async fn fetch_json(&mut self) -> Result<Value, String> {
+    /// The post page's HTML, after following `fb.watch` and `/watch/?v=` links to the reel.
+    async fn fetch_page(&mut self) -> Result<String, ScrapeError> {
         if self.url.contains("fb.watch") || self.url.contains("/watch/?v") {
-            if let Ok(resp) = self.get(&self.url).await {
-                if let Some(video_id) = resp.url().path_segments()
-                    .and_then(|segments| segments.skip_while(|s| *s != "videos").nth(1))
-                {
-                    self.url = format!("https://www.facebook.com/reel/{}", video_id);
-                } else {
-                    return Err("video not found".into());
-                }
-            } else {
-                return Err("video request failed".into());
-            }
+            let resp = self.get(&self.url).await?;
+            let video_id = reqwest::Url::parse(&resp.url).ok()
+                .and_then(|url| url.path_segments()?.skip_while(|s| *s != "videos").nth(1).map(str::to_string))
+                .ok_or(ScrapeError::NotFound)?;
+            self.url = format!("https://www.facebook.com/reel/{}", video_id);
+        }
+        let resp = self.get(&self.url).await?;
+        if let Some(e) = resp.error() {
+            return Err(e);
+        }
+        if resp.url.contains("facebook.com/login") {
+            return Err(ScrapeError::LoginRequired);
+        }
+        Ok(resp.text())
+    }
+
+    async fn fetch_json(&mut self) -> Result<Value, ScrapeError> {
+        let text = self.fetch_page().await?;
+        if self.is_story() {
+            return Self::parse_story(&text).ok_or_else(|| match text.contains("id=\"login_form\"") {
+                true => ScrapeError::LoginRequired,
+                false => ScrapeError::NotFound,
+            });
         }
-        // return Ok("parsed".into());
-        let resp = self.get(&self.url).await.map_err(|e| format!("Request error: {}", e))?;
-        if resp.status() != 200 {
-            return Err(format!("Failed to fetch page: {}", resp.status()));
+        Self::parse_html(&text).or_else(|e| Self::parse_photos(&text).ok_or(e))
+    }
+
+    /// The post's feedback object holding its first page of comments, scored across all of the
+    /// page's Relay JSON.
+    fn feedback(text: &str) -> Option<Value> {
+        let page = Self::scripts(text);
+        let candidates = locate::find_objects(&page, &|o| o.contains_key("comment_rendering_instance_for_feed_location"));
+        locate::best(candidates, |m| Some(m.value.get("total_comment_count").is_some() as i64)).map(|m| m.value.clone())
+    }
+
+    /// Every `application/json` script of the page that parses, in page order, as one array so
+    /// candidates from all of them can be scored together.
+    fn scripts(text: &str) -> Value {
+        let document = Html::parse_document(text);
+        let script_sel = Selector::parse("script[type='application/json']").unwrap();
+        Value::Array(document.select(&script_sel)
+            .filter_map(|script| serde_json::from_str(script.text().next().unwrap_or("").trim()).ok())
+            .collect())
+    }
+
+    /// A page of comments: the first comes with the post page, later ones from the
+    /// `CommentsListComponentsPaginationQuery` GraphQL query, whose id is read from the page.
+    pub async fn get_comments(&mut self, cursor: Option<String>) -> Result<Page<Comment>, ScrapeError> {
+        let text = self.fetch_page().await?;
+        let feedback = Self::feedback(&text)
+            .ok_or_else(|| ScrapeError::UpstreamChanged("no comments on the page".into()))?;
+        let Some(cursor) = cursor else {
+            let comments = feedback.pointer("/comment_rendering_instance_for_feed_location/comments").unwrap_or(&Value::Null);
+            return Ok(Self::cut_comments(comments));
+        };
+
+        let find = |pattern: &str| Regex::new(pattern).unwrap().captures(&text).map(|cap| cap[1].to_string());
+        let doc_id = find(r#"CommentsListComponentsPaginationQuery_facebookRelayOperation",\[\],\(?function\([^)]*\)\{e\.exports="(\d+)""#)
+            .ok_or_else(|| ScrapeError::UpstreamChanged("no comments query id on the page".into()))?;
+        let lsd = find(r#"\["LSD",\[\],\{"token":"([^"]+)""#).unwrap_or_default();
+        let variables = json!({
+            "commentsAfterCount": -1,
+            "commentsAfterCursor": cursor,
+            "feedLocation": "TAHOE",
+            "focusCommentID": null,
+            "id": string(feedback.get("id")),
+            "scale": 1,
+            "useDefaultActor": false,
+        });
+        let form = json!({
+            "av": "0",
+            "__user": "0",
+            "__a": "1",
+            "__comet_req": "15",
+            "lsd": lsd,
+            "fb_api_caller_class": "RelayModern",
+            "fb_api_req_friendly_name": "CommentsListComponentsPaginationQuery",
+            "variables": variables.to_string(),
+            "server_timestamps": "true",
+            "doc_id": doc_id,
+        });
+        let mut headers = Self::headers();
+        if let Ok(value) = reqwest::header::HeaderValue::from_str(&lsd) {
+            headers.insert("X-FB-LSD", value);
+        }
+        let resp = self.http.post_form("https://www.facebook.com/api/graphql/", headers, &form).await?;
+        if let Some(e) = resp.error() {
+            return Err(e);
+        }
+        // GraphQL answers can be prefixed with `for (;;);` and followed by streamed chunks.
+        let body = resp.text();
+        let first = body.trim_start_matches("for (;;);").lines().next().unwrap_or("");
+        let data: Value = serde_json::from_str(first)
+            .map_err(|_| ScrapeError::UpstreamChanged("Invalid JSON in comments response".into()))?;
+        let comments = data.pointer("/data/node/comment_rendering_instance_for_feed_location/comments")
+            .ok_or_else(|| ScrapeError::UpstreamChanged("no comments in response".into()))?;
+        Ok(Self::cut_comments(comments))
+    }
+
+    fn comment(node: &Value) -> Comment {
+        let author = node.get("author");
+        let feedback = node.get("feedback");
+        let replies = feedback.and_then(|f| f.pointer("/replies_connection/edges")).and_then(|e| e.as_array()).cloned().unwrap_or_default();
+        Comment {
+            id: string(node.get("legacy_fbid")).or_else(|| string(node.get("id"))),
+            text: string(node.pointer("/body/text")),
+            author: Author {
+                id: string(author.and_then(|a| a.get("id"))),
+                name: string(author.and_then(|a| a.get("name"))),
+                image: string(author.and_then(|a| a.pointer("/profile_picture_depth_0/uri"))),
+                ..Default::default()
+            },
+            likes: number(feedback.and_then(|f| f.pointer("/reactors/count"))),
+            timestamp: number(node.get("created_time")),
+            reply_count: number(feedback.and_then(|f| f.pointer("/replies_fields/total_count"))),
+            replies: replies.iter().filter_map(|e| e.get("node")).map(Self::comment).collect(),
+        }
+    }
+
+    /// Reads a Relay `comments` connection.
+    pub fn cut_comments(comments: &Value) -> Page<Comment> {
+        let edges = comments.get("edges").and_then(|e| e.as_array()).cloned().unwrap_or_default();
+        Page {
+            items: edges.iter().filter_map(|e| e.get("node")).map(Self::comment).collect(),
+            cursor: string(comments.pointer("/page_info/end_cursor")),
+            has_more: boolean(comments.pointer("/page_info/has_next_page")).unwrap_or(false),
+        }
+    }
+
+    /// Per-reaction counts from the feedback's `top_reactions`; types Facebook left out stay `None`.
+    fn reactions(feedback: Option<&Value>) -> Option<Reactions> {
+        // Comments and replies under the feedback carry their own `top_reactions`; those sit in
+        // a connection's `edges`. Of the rest, the one closest to the feedback is the post's.
+        let edges = feedback
+            .and_then(|f| locate::best(locate::find_all(f, "top_reactions"), |m| {
+                (!m.path.contains("/edges/")).then(|| -(m.path.matches('/').count() as i64))
+            }))
+            .map(|m| m.value)
+            .and_then(|t| t.get("edges"))
+            .and_then(|e| e.as_array())?;
+        let mut reactions = Reactions::default();
+        for edge in edges {
+            let count = number(edge.get("reaction_count"));
+            match edge.pointer("/node/reaction_type").and_then(|t| t.as_str()) {
+                Some("LIKE") => reactions.like = count,
+                Some("LOVE") => reactions.love = count,
+                Some("HAHA") => reactions.haha = count,
+                Some("WOW") => reactions.wow = count,
+                Some("SORRY") | Some("SAD") => reactions.sad = count,
+                Some("ANGER") | Some("ANGRY") => reactions.angry = count,
+                _ => {}
+            }
         }
-        let text = resp.text().await.map_err(|e| format!("Read body failed: {}", e))?;
-        let document = Html::parse_document(&text);
+        Some(reactions)
+    }
+
+    /// Finds the story bucket (its owner and `unified_stories` cards) and folds it onto the
+    /// payload root as `bucket`.
+    pub fn parse_story(text: &str) -> Option<Value> {
+        let document = Html::parse_document(text);
         let script_sel = Selector::parse("script[type='application/json']").unwrap();
-        let mut preferred_thumbnail: Option<Value> = None;
-        let mut browser_native_hd_url: Option<Value> = None;
-        let mut json_data: Option<Value> = None;
 
         for script in document.select(&script_sel) {
             let script_text = script.text().next().unwrap_or("").trim();
-            if script_text.contains("preferred_thumbnail") && json_data.is_none() {
-                let parsed: Value = serde_json::from_str(&script_text).map_err(|_| "Invalid JSON")?;
-                preferred_thumbnail = Self::get_nested_value(&parsed, "preferred_thumbnail").cloned();
-                browser_native_hd_url = Self::get_nested_value(&parsed, "browser_native_hd_url").cloned();
-                json_data = Some(parsed);
+            if !script_text.contains("unified_stories") {
+                continue;
             }
+            let Ok(mut parsed) = serde_json::from_str::<Value>(script_text) else {
+                continue;
+            };
+            let candidates = locate::find_objects(&parsed, &|o| o.contains_key("unified_stories"));
+            let Some(bucket) = locate::best(candidates, |m| Some(m.value.get("story_bucket_owner").is_some() as i64)) else {
+                continue;
+            };
+            let bucket = bucket.value.clone();
+            parsed["bucket"] = bucket;
+            parsed["platform"] = json!("facebook");
+            return Some(parsed);
         }
+        None
+    }
+
+    /// The `Photo` nodes attached to a post, one per photo of a multi-photo post.
+    fn story_photos(story: &Value) -> Vec<Value> {
+        let attachments = story.get("attachments").and_then(|a| a.as_array()).cloned().unwrap_or_default();
+        attachments.iter()
+            .filter_map(|a| a.pointer("/styles/attachment"))
+            .flat_map(|a| match a.pointer("/all_subattachments/nodes").and_then(|n| n.as_array()) {
+                Some(nodes) => nodes.iter().filter_map(|n| n.get("media")).cloned().collect(),
+                None => a.get("media").cloned().into_iter().collect::<Vec<_>>(),
+            })
+            .filter(|m| m.get("__typename").and_then(|t| t.as_str()) == Some("Photo"))
+            .collect()
+    }
+
+    /// Finds the photo viewer (`currMedia`) or a post whose attachments are photos, and folds
+    /// the post, its owner and its `Photo` nodes onto the payload root.
+    pub fn parse_photos(text: &str) -> Option<Value> {
+        let document = Html::parse_document(text);
+        let script_sel = Selector::parse("script[type='application/json']").unwrap();
 
         for script in document.select(&script_sel) {
             let script_text = script.text().next().unwrap_or("").trim();
-            let keywords = ["base_url", "total_comment_count"];
-            if keywords.iter().all(|k| script_text.contains(k)) {
-                let mut parsed: Value = serde_json::from_str(&script_text).map_err(|_| "Invalid JSON")?;
-
-                let mut data = Self::get_nested_value(&parsed, "data").cloned();
-                let owner = Self::get_nested_value(&parsed, "owner_as_page").cloned()
-                    .or_else(|| data.as_ref().and_then(|d| Self::get_nested_value(d, "owner").cloned()));
-
-                if let Some(d) = data.as_mut() {
-                    if d.get("title").and_then(|t| t.get("text")).is_none() {
-                        if let Some(message) = d.get("message").and_then(|m| m.get("text")) {
-                            d["title"] = json!({ "text": message });
-                        }
-                    }
-                }
+            if !script_text.contains("\"Photo\"") {
+                continue;
+            }
+            let Ok(mut parsed) = serde_json::from_str::<Value>(script_text) else {
+                continue;
+            };
 
-                if browser_native_hd_url.is_none() {
-                    let reps = Self::get_nested_value(&parsed, "representations").and_then(|r| r.as_array().cloned()).unwrap_or_default();
-                    let mut deaf_media = json!({});
-                    for rep in reps {
-                        if let Some(mime) = rep.get("mime_type").and_then(|m| m.as_str()) {
-                            if mime.to_lowercase().contains("video") {
-                                deaf_media["video_url"] = rep.get("base_url").cloned().unwrap_or(json!("N/A"));
-                            } else if mime.to_lowercase().contains("audio") {
-                                deaf_media["audio_url"] = rep.get("base_url").cloned().unwrap_or(json!("N/A"));
-                            }
-                        }
-                    }
-                    // browser_native_hd_url = deaf_media.get("video_url").cloned();
-                    parsed["deaf_media"] = deaf_media;
+            let is_photo = |m: &Value| m.get("__typename").and_then(|t| t.as_str()) == Some("Photo");
+            let viewer = locate::best(locate::find_all(&parsed, "currMedia"), |m| Some(is_photo(m.value) as i64 * 2 + m.value.get("owner").is_some() as i64));
+            let story = locate::best(
+                locate::find_objects(&parsed, &|o| o.contains_key("attachments") && o.contains_key("actors")),
+                |m| Some(Self::story_photos(m.value).len() as i64),
+            );
+            let (located, post, owner, photos) = if let Some(media) = viewer.filter(|m| is_photo(m.value)) {
+                (media.path, media.value.clone(), media.value.get("owner").cloned(), vec![media.value.clone()])
+            } else if let Some(story) = story {
+                (story.path, story.value.clone(), story.value.pointer("/actors/0").cloned(), Self::story_photos(story.value))
+            } else {
+                continue;
+            };
+            if photos.is_empty() {
+                continue;
+            }
+
+            parsed["post"] = post;
+            parsed["owner"] = owner.unwrap_or(json!({}));
+            parsed["photos"] = Value::Array(photos);
+            parsed["platform"] = json!("facebook");
+            parsed["located"] = json!({ "post": located });
+            return Some(parsed);
+        }
+        None
+    }
+
+    /// Finds the Relay payloads carrying the video, scoring candidates across every JSON script
+    /// of the page, and folds the bits `cut` needs onto the root of the script holding the story.
+    /// `located` records the JSON path each bit was taken from, starting at the script's index.
+    pub fn parse_html(text: &str) -> Result<Value, ScrapeError> {
+        let page = Self::scripts(text);
+        let mut located = json!({});
+        let stories = locate::find_all(&page, "data");
+        let rendition_sets = locate::find_all(&page, "representations");
+
+        // The player: the video node with its thumbnail and progressive files. Related videos
+        // have players too, so the one a story node with feedback and the DASH renditions are
+        // about wins.
+        let told = |id: &Option<String>| {
+            let story = stories.iter().any(|m| m.value.get("feedback").is_some() && string(m.value.get("id")) == *id);
+            let reps = rendition_sets.iter().any(|m| m.parent(&page).is_some_and(|p| string(p.get("video_id")) == *id));
+            story as i64 * 8 + reps as i64 * 4
+        };
+        let candidates = locate::find_objects(&page, &|o| o.contains_key("preferred_thumbnail"));
+        let video = locate::best(candidates, |m| {
+            let id = string(m.value.get("id"));
+            Some(id.as_ref().map_or(0, |_| told(&id))
+                + m.value.get("browser_native_hd_url").is_some() as i64 * 2
+                + (m.value.get("__typename").and_then(|t| t.as_str()) == Some("Video")) as i64)
+        });
+        if let Some(m) = &video {
+            located["video"] = json!(m.path);
+        }
+        let video = video.map(|m| m.value.clone());
+        let video_id = video.as_ref().and_then(|v| string(v.get("id")));
+        let is_video = |v: Option<&Value>| video_id.is_some() && string(v) == video_id;
+
+        // The story node: the one about our video, else one with feedback to read.
+        let data = locate::best(stories.clone(), |m| {
+            let node = m.value.as_object()?;
+            let (about, feedback) = (is_video(node.get("id")), node.contains_key("feedback"));
+            (about || feedback).then_some(about as i64 * 4 + feedback as i64 * 2 + node.contains_key("owner") as i64)
+        });
+        let reps = locate::best(rendition_sets.clone(), |m| {
+            m.value.as_array()?;
+            Some(m.parent(&page).is_some_and(|p| is_video(p.get("video_id"))) as i64)
+        });
+        // Broadcasts may have no DASH prefetch; their renditions come from the player's manifest.
+        let live = video.as_ref().is_some_and(Self::is_broadcast);
+        let Some(story) = data.as_ref().filter(|_| reps.is_some() || live) else {
+            if text.contains("This content isn't available") {
+                return Err(ScrapeError::NotFound);
+            }
+            return Err(ScrapeError::UpstreamChanged("No valid JSON script found".into()));
+        };
+        let script = story.path.split('/').nth(1).and_then(|i| i.parse::<usize>().ok()).unwrap_or(0);
+        let mut parsed = page[script].clone();
+
+        // Only a page owner under the chosen story node or next to the video id is the video's;
+        // without one, the story node's own `owner` is used.
+        let data_path = data.as_ref().map(|m| format!("{}/", m.path));
+        let page_owner = locate::best(locate::find_all(&page, "owner_as_page"), |m| {
+            let under_data = data_path.as_ref().is_some_and(|p| m.path.starts_with(p.as_str()));
+            let near_video = m.parent(&page).is_some_and(|p| is_video(p.get("id")) || is_video(p.get("video_id")));
+            (under_data || near_video).then_some(under_data as i64 * 2 + near_video as i64)
+        });
+
+        for (key, m) in [("data", &data), ("representations", &reps), ("owner", &page_owner)] {
+            if let Some(m) = m {
+                located[key] = json!(m.path);
+            }
+        }
+        let mut data = data.map(|m| m.value.clone()).unwrap_or(json!({}));
+        let mut reps = reps.map(|m| m.value.clone()).unwrap_or(json!([]));
+        if reps.as_array().is_none_or(|r| r.is_empty())
+            && let Some(mpd) = video.as_ref().and_then(|v| v.get("dash_manifest")).and_then(|m| m.as_str())
+        {
+            located["representations"] = json!(format!("{}/dash_manifest", located["video"].as_str().unwrap_or("")));
+            reps = Value::Array(Self::manifest_representations(mpd));
+        }
+        let owner = match page_owner {
+            Some(m) => m.value.clone(),
+            None => {
+                if data.get("owner").is_some() {
+                    located["owner"] = json!(format!("{}/owner", located["data"].as_str().unwrap_or("")));
                 }
+                data.get("owner").cloned().unwrap_or(json!({}))
+            }
+        };
 
-                parsed["data"] = data.unwrap_or(json!({}));
-                parsed["owner"] = owner.unwrap_or(json!({}));
-                parsed["platform"] = json!("facebook");
-                parsed["preferred_thumbnail"] = preferred_thumbnail.unwrap_or(json!({}));
+        if data.get("title").and_then(|t| t.get("text")).is_none()
+            && let Some(message) = data.get("message").and_then(|m| m.get("text")).cloned()
+        {
+            data["title"] = json!({ "text": message });
+        }
 
-                return Ok(parsed);
+        let field = |keys: &[&str]| keys.iter()
+            .find_map(|k| video.as_ref().and_then(|v| v.get(*k)).filter(|u| !u.is_null()))
+            .cloned();
+        let hd_url = field(&["browser_native_hd_url", "playable_url_quality_hd"]);
+        let sd_url = field(&["browser_native_sd_url", "playable_url"]);
+        for key in LIVE_FIELDS {
+            if let Some(value) = field(&[key]) {
+                parsed[key] = value;
             }
         }
+        parsed["data"] = data;
+        parsed["owner"] = owner;
+        parsed["platform"] = json!("facebook");
+        parsed["preferred_thumbnail"] = video.as_ref().and_then(|v| v.get("preferred_thumbnail")).cloned().unwrap_or(json!({}));
+        parsed["representations"] = reps;
+        for (key, url) in [("browser_native_hd_url", hd_url), ("browser_native_sd_url", sd_url)] {
+            if let Some(url) = url {
+                parsed[key] = url;
+            }
+        }
+        parsed["located"] = located;
+        Ok(parsed)
+    }
 
-        Err("No valid JSON script found".into())
+    /// Live now, or a replay of something that was.
+    fn is_broadcast(video: &Value) -> bool {
+        boolean(video.get("is_live_streaming")) == Some(true) || string(video.get("broadcast_status")).is_some()
     }
 
-    fn err(&self, message: &str, error_message: &str) -> Value {
-        json!({ "error": true, "message": message, "error_message": error_message })
+    /// `ongoing` while `broadcast_status` is `LIVE` (or, without a status, while `is_live_streaming`),
+    /// with the DASH manifest URL, or the HLS one when that is all there is.
+    fn live(data: &Value) -> Option<Live> {
+        if !Self::is_broadcast(data) {
+            return None;
+        }
+        let ongoing = match string(data.get("broadcast_status")) {
+            Some(status) => status == "LIVE",
+            None => boolean(data.get("is_live_streaming")) == Some(true),
+        };
+        let (manifest, protocol) = match (string(data.get("dash_manifest_url")), string(data.get("hls_playlist_url"))) {
+            (Some(url), _) => (Some(url), Some("dash")),
+            (None, Some(url)) => (Some(url), Some("hls")),
+            (None, None) => (None, None),
+        };
+        Some(Live { ongoing, manifest, protocol: protocol.map(str::to_string), ..Default::default() })
     }
 
-    pub async fn get_data(&mut self) -> (Value, u16) {
-        let data = match self.fetch_json().await {
-            Ok(d) => d,
-            Err(e) => return (self.err(&e, &e), 502),
+    /// Representations of an inline MPD, in the shape of the Relay `representations` entries.
+    /// Live manifests use segment templates rather than one file per rendition, so those come
+    /// without a `base_url`.
+    pub fn manifest_representations(mpd: &str) -> Vec<Value> {
+        let sets = Regex::new(r"(?s)<AdaptationSet\b([^>]*)>(.*?)</AdaptationSet>").unwrap();
+        let reps = Regex::new(r"(?s)<Representation\b([^>]*?)(?:/>|>(.*?)</Representation>)").unwrap();
+        let base_url = Regex::new(r"(?s)<BaseURL>(.*?)</BaseURL>").unwrap();
+        let attr = |attrs: &str, name: &str| {
+            Regex::new(&format!(r#"\b{}="([^"]*)""#, name)).unwrap()
+                .captures(attrs)
+                .map(|cap| cap[1].to_string())
         };
-        
-        if !self.cut {
-            return (data.clone(), 200);
-        }
-
-        let preferred_thumbnail = Self::get_nested_value(&data, "preferred_thumbnail").cloned();
-        let mut browser_native_hd_url = Self::get_nested_value(&data, "browser_native_hd_url").cloned();
-        let representations = Self::get_nested_value(&data, "representations").cloned();
-        let mut content = Self::get_nested_value(&data, "data").cloned().unwrap_or(json!({}));
-        let owner = Self::get_nested_value(&data, "owner_as_page")
-            .cloned()
-            .or_else(|| Self::get_nested_value(&content, "owner").cloned())
-            .unwrap_or(json!({}));
-
-        if content.get("title").and_then(|t| t.get("text")).is_none() {
-            if let Some(message_text) = content.get("message").and_then(|m| m.get("text")) {
-                content["title"] = json!({ "text": message_text });
+        let unescape = |s: &str| s.replace("&amp;", "&").replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"");
+
+        let mut found = vec![];
+        for set in sets.captures_iter(mpd) {
+            let set_mime = attr(&set[1], "mimeType");
+            for rep in reps.captures_iter(&set[2]) {
+                let attrs = &rep[1];
+                let number = |name: &str| attr(attrs, name).and_then(|v| v.parse::<u64>().ok());
+                found.push(json!({
+                    "representation_id": attr(attrs, "id"),
+                    "mime_type": attr(attrs, "mimeType").or(set_mime.clone()),
+                    "codecs": attr(attrs, "codecs"),
+                    "bandwidth": number("bandwidth"),
+                    "width": number("width").unwrap_or(0),
+                    "height": number("height").unwrap_or(0),
+                    "base_url": rep.get(2).and_then(|body| base_url.captures(body.as_str())).map(|cap| unescape(cap[1].trim())),
+                }));
             }
         }
+        found
+    }
 
-        let desc = content.get("title").and_then(|t| t.get("text")).cloned().unwrap_or(json!(""));
-
-        let comment_count = content
-            .get("feedback")
-            .and_then(|f| f.get("total_comment_count"))
-            .cloned()
-            .unwrap_or(json!(0));
-        let reactions = content
-            .get("feedback")
-            .and_then(|f| f.get("reaction_count"))
-            .and_then(|r| r.get("count"))
-            .cloned()
-            .unwrap_or(json!(0));
-        let plays = content
-            .get("feedback")
-            .and_then(|f| f.get("video_view_count_renderer"))
-            .and_then(|v| v.get("feedback"))
-            .and_then(|fb| fb.get("play_count"))
-            .cloned()
-            .unwrap_or(json!(0));
-        let post_views = content.get("feedback")
-            .and_then(|f| f.get("video_view_count_renderer"))
-            .and_then(|v| v.get("feedback"))
-            .and_then(|fb| fb.get("video_post_view_count")).cloned().unwrap_or(json!(0));
-
-        let deaf_media: Option<Value> = if browser_native_hd_url.is_none() {
-            let mut media = json!({});
-            if let Some(reps) = representations.and_then(|r| r.as_array().cloned()) {
-                for rep in reps {
-                    if let Some(mime) = rep.get("mime_type").and_then(|m| m.as_str()) {
-                        if mime.to_lowercase().contains("video") {
-                            media["video_url"] = rep.get("base_url").cloned().unwrap_or(json!("N/A"));
-                        } else if mime.to_lowercase().contains("audio") {
-                            media["audio_url"] = rep.get("base_url").cloned().unwrap_or(json!("N/A"));
-                        }
-                    }
-                }
+    fn author(owner: &Value) -> Author {
+        Author {
+            id: string(owner.get("id")),
+            name: string(owner.get("name")),
+            verified: boolean(owner.get("is_verified")),
+            image: string(owner.get("profile_picture").and_then(|p| p.get("uri"))),
+            ..Default::default()
+        }
+    }
+
+    /// Every size Facebook lists for a `Photo` node, largest first, named `WxH`.
+    fn photo_variants(photo: &Value) -> Vec<Variant> {
+        let mut variants: Vec<Variant> = vec![];
+        for key in ["image", "viewer_image", "photo_image", "large_share_image", "full_image"] {
+            let Some(image) = photo.get(key) else { continue };
+            let Some(address) = string(image.get("uri")) else { continue };
+            if variants.iter().any(|v| v.address == address) {
+                continue;
             }
-            browser_native_hd_url = media.get("video_url").cloned();
-            Some(media)
-        } else {
-            None
-        };
+            let width = number(image.get("width"));
+            let height = number(image.get("height"));
+            variants.push(Variant {
+                name: Some(format!("{}x{}", width.unwrap_or(0), height.unwrap_or(0))),
+                address,
+                width,
+                height,
+                ..Default::default()
+            });
+        }
+        variants.sort_by_key(|v| std::cmp::Reverse(v.width.unwrap_or(0) * v.height.unwrap_or(0)));
+        variants
+    }
 
-        let cut_data = json!({
-            "platform": "facebook",
-            "is_video": true,
-            "content": {
-                "id": content.get("id").cloned().unwrap_or(json!("N/A")),
-                "desc": desc,
-                "cover": preferred_thumbnail.as_ref().and_then(|p| p.get("image")).and_then(|i| i.get("uri")).cloned().unwrap_or(json!("N/A")),
-                "comment": comment_count,
-                "reactions": reactions,
-                "plays": plays,
-                "post_views": post_views
+    /// Cut output for what `parse_photos` found: one image item per photo.
+    fn cut_photos(data: &Value) -> Post {
+        let post = data.get("post").cloned().unwrap_or(json!({}));
+        let photos = data.get("photos").and_then(|p| p.as_array()).cloned().unwrap_or_default();
+        let media: Vec<MediaItem> = photos.iter().map(|photo| {
+            let variants = Self::photo_variants(photo);
+            let best = variants.first();
+            MediaItem {
+                id: string(photo.get("id")),
+                kind: MediaKind::Image,
+                address: best.map(|v| v.address.clone()),
+                width: best.and_then(|v| v.width),
+                height: best.and_then(|v| v.height),
+                alt: string(photo.get("accessibility_caption")),
+                variants,
+                ..Default::default()
+            }
+        }).collect();
+        let feedback = post.get("feedback");
+
+        Post {
+            platform: "facebook".to_string(),
+            content: Content {
+                id: string(post.get("post_id")).or_else(|| string(post.get("id"))),
+                desc: string(post.pointer("/message/text")),
+                cover: media.first().and_then(|m| m.address.clone()),
+                comments: number(feedback.and_then(|f| f.get("total_comment_count"))),
+                likes: number(feedback.and_then(|f| f.pointer("/reaction_count/count"))),
+                reactions: Self::reactions(feedback),
+                ..Default::default()
             },
-            "author": owner,
-            "media": [
-                {
-                    "is_video": true,
-                    "id": content.get("id").cloned().unwrap_or(json!("N/A")),
-                    "address": browser_native_hd_url.clone().unwrap_or(json!("N/A")),
-                    "cover": preferred_thumbnail.as_ref().and_then(|p| p.get("image")).and_then(|i| i.get("uri")).cloned().unwrap_or(json!("N/A"))
+            author: Self::author(data.get("owner").unwrap_or(&Value::Null)),
+            media,
+            music: None,
+        }
+    }
+
+    /// One item per story card: its video (hd/sd files) or photo, with when it was posted.
+    fn story_card(node: &Value) -> Option<MediaItem> {
+        let media = node.pointer("/attachments/0/media")?;
+        let timestamp = number(node.get("creation_time"));
+        if media.get("__typename").and_then(|t| t.as_str()) == Some("Photo") {
+            let variants = Self::photo_variants(media);
+            let best = variants.first();
+            return Some(MediaItem {
+                id: string(media.get("id")),
+                kind: MediaKind::Image,
+                address: best.map(|v| v.address.clone()),
+                width: best.and_then(|v| v.width),
+                height: best.and_then(|v| v.height),
+                alt: string(media.get("accessibility_caption")),
+                timestamp,
+                variants,
+                ..Default::default()
+            });
+        }
+        let variants: Vec<Variant> = [
+            ("hd", "browser_native_hd_url"),
+            ("sd", "browser_native_sd_url"),
+            ("hd", "playable_url_quality_hd"),
+            ("sd", "playable_url"),
+        ]
+            .into_iter()
+            .filter_map(|(name, key)| Some(Variant {
+                name: Some(name.to_string()),
+                address: string(media.get(key))?,
+                ..Default::default()
+            }))
+            .fold(vec![], |mut variants: Vec<Variant>, v| {
+                if !variants.iter().any(|seen| seen.name == v.name) {
+                    variants.push(v);
                 }
-            ],
-            "deaf_media": deaf_media.unwrap_or(json!({}))
-        });
+                variants
+            });
+        Some(MediaItem {
+            id: string(media.get("id")),
+            kind: MediaKind::Video,
+            address: variants.first().map(|v| v.address.clone()),
+            cover: string(media.pointer("/previewImage/uri")),
+            width: number(media.get("width")),
+            height: number(media.get("height")),
+            timestamp,
+            variants,
+            ..Default::default()
+        })
+    }
+
+    /// Cut output for what `parse_story` found.
+    fn cut_story(data: &Value) -> Post {
+        let bucket = data.get("bucket").cloned().unwrap_or(json!({}));
+        let edges = bucket.pointer("/unified_stories/edges").and_then(|e| e.as_array()).cloned().unwrap_or_default();
+        let media: Vec<MediaItem> = edges.iter()
+            .filter_map(|e| e.get("node"))
+            .filter_map(Self::story_card)
+            .collect();
+        Post {
+            platform: "facebook".to_string(),
+            content: Content {
+                id: string(bucket.get("id")),
+                cover: media.first().and_then(|m| m.cover.clone().or(m.address.clone())),
+                ..Default::default()
+            },
+            author: Self::author(bucket.get("story_bucket_owner").unwrap_or(&Value::Null)),
+            media,
+            music: None,
+        }
+    }
+
+    pub fn cut(data: &Value) -> Post {
+        if data.get("bucket").is_some() {
+            return Self::cut_story(data);
+        }
+        if data.get("photos").is_some() {
+            return Self::cut_photos(data);
+        }
+        let cover = string(data.pointer("/preferred_thumbnail/image/uri"));
+        let mut content = data.get("data").cloned().unwrap_or(json!({}));
+        let owner = data.get("owner").cloned().unwrap_or(json!({}));
+
+        if content.get("title").and_then(|t| t.get("text")).is_none()
+            && let Some(message_text) = content.get("message").and_then(|m| m.get("text"))
+        {
+            content["title"] = json!({ "text": message_text });
+        }
+
+        let feedback = content.get("feedback");
+        let view_counts = feedback
+            .and_then(|f| f.get("video_view_count_renderer"))
+            .and_then(|v| v.get("feedback"));
+        let id = string(content.get("id"));
+
+        // Renditions without a file of their own only exist inside the manifest.
+        let manifest = string(data.get("dash_manifest_url"));
+        let (renditions, variants): (Vec<Variant>, Vec<Variant>) = Self::variants(data).into_iter()
+            .partition(|v| Some(&v.address) == manifest.as_ref());
+        let mut media_item = MediaItem {
+            id: id.clone(),
+            kind: MediaKind::Video,
+            cover: cover.clone(),
+            variants,
+            ..Default::default()
+        };
+        let dash_video = media_item.variants.iter().find(|v| v.mime_type.as_deref().is_some_and(|m| m.starts_with("video/")));
+        let dash_audio = media_item.variants.iter().find(|v| v.mime_type.as_deref().is_some_and(|m| m.starts_with("audio/")));
+        media_item.audio = dash_audio.map(|v| v.address.clone());
+        // Progressive files have sound, so a silent DASH rendition is the address only without them.
+        let progressive = |name: &str| media_item.variants.iter().find(|v| v.name.as_deref() == Some(name));
+        let best = progressive("hd").or_else(|| progressive("sd")).or(dash_video);
+        if let Some(best) = best.cloned() {
+            media_item.address = Some(best.address);
+            media_item.width = best.width;
+            media_item.height = best.height;
+        }
+        media_item.live = Self::live(data).map(|live| Live { renditions, ..live });
+        if media_item.address.is_none()
+            && let Some(live) = &media_item.live
+        {
+            media_item.address = live.manifest.clone();
+            let best = live.renditions.iter().find(|v| v.mime_type.as_deref().is_some_and(|m| m.starts_with("video/")));
+            media_item.width = best.and_then(|v| v.width);
+            media_item.height = best.and_then(|v| v.height);
+        }
+
+        Post {
+            platform: "facebook".to_string(),
+            content: Content {
+                id,
+                desc: string(content.get("title").and_then(|t| t.get("text"))),
+                cover,
+                comments: number(feedback.and_then(|f| f.get("total_comment_count"))),
+                likes: number(feedback.and_then(|f| f.get("reaction_count")).and_then(|r| r.get("count"))),
+                reactions: Self::reactions(feedback),
+                plays: number(view_counts.and_then(|fb| fb.get("play_count"))),
+                views: number(view_counts.and_then(|fb| fb.get("video_post_view_count"))),
+                ..Default::default()
+            },
+            author: Self::author(&owner),
+            media: vec![media_item],
+            music: None,
+        }
+    }
 
-        (cut_data, 200)
+    /// Quality ladder: the progressive `hd`/`sd` files (with sound), then every DASH
+    /// representation best-first, video-only before audio-only. Live renditions have no file of
+    /// their own, so their address is the manifest and `cut` moves them under `live`.
+    fn variants(data: &Value) -> Vec<Variant> {
+        let mut variants: Vec<Variant> = [("hd", "browser_native_hd_url"), ("sd", "browser_native_sd_url")]
+            .into_iter()
+            .filter_map(|(name, key)| {
+                Some(Variant {
+                    name: Some(name.to_string()),
+                    address: string(data.get(key))?,
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let reps = data.get("representations").and_then(|r| r.as_array().cloned()).unwrap_or_default();
+        let mut dash: Vec<Variant> = reps.iter()
+            .filter_map(|rep| {
+                let mime_type = string(rep.get("mime_type"))?.to_lowercase();
+                let width = number(rep.get("width")).filter(|w| *w > 0);
+                let height = number(rep.get("height")).filter(|h| *h > 0);
+                let bitrate = number(rep.get("bandwidth"));
+                let name = match (mime_type.starts_with("audio/"), width.min(height)) {
+                    (true, _) => format!("audio_{}k", bitrate.unwrap_or(0) / 1000),
+                    (false, Some(side)) => format!("{}p", side),
+                    (false, None) => string(rep.get("representation_id"))?,
+                };
+                Some(Variant {
+                    name: Some(name),
+                    address: string(rep.get("base_url")).or_else(|| string(data.get("dash_manifest_url")))?,
+                    width,
+                    height,
+                    bitrate,
+                    codec: string(rep.get("codecs")),
+                    mime_type: Some(mime_type),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        dash.sort_by_key(|v| (v.mime_type.as_deref().is_some_and(|m| m.starts_with("audio/")), std::cmp::Reverse(v.bitrate)));
+        variants.extend(dash);
+        variants
+    }
+
+    pub async fn get_data(&mut self) -> Outcome {
+        let data = self.fetch_json().await?;
+        Self::extract(data, self.cut)
     }
 
+    pub fn extract(data: Value, cut: bool) -> Outcome {
+        if !cut {
+            return Ok(Scraped::Full(data));
+        }
+
+        Ok(Scraped::Cut(Box::new(Self::cut(&data))))
+    }
+}
+
+impl Platform for Facebook {
+    fn matches(url: &str) -> bool {
+        Regex::new(r"(facebook\.com/.*/|facebook\.com/(photo|permalink)\.php\?|fb\.watch/.*/)").unwrap().is_match(url)
+    }
+
+    fn name() -> &'static str {
+        "facebook"
+    }
+
+    fn normalize(url: &str) -> String {
+        url.replace("web.facebook", "www.facebook")
+    }
+
+    fn from_url(url: &str, cut: bool, http: Http) -> Self {
+        Self::new(url, cut, http)
+    }
+
+    fn media_hosts() -> &'static [&'static str] {
+        &["fbcdn.net"]
+    }
+
+    fn media_headers() -> reqwest::header::HeaderMap {
+        Self::headers()
+    }
+
+    fn fetch(&mut self) -> BoxFuture<'_, Outcome> {
+        Box::pin(self.get_data())
+    }
+
+    fn comments(&mut self, cursor: Option<String>) -> BoxFuture<'_, Result<Page<Comment>, ScrapeError>> {
+        Box::pin(self.get_comments(cursor))
+    }
 }
 
 #[tokio::test]
+#[ignore = "hits the live site"]
 async fn facebook() {
-    let client = reqwest::Client::new();
+    let http = Http::new(reqwest::Client::new(), super::http::Mode::Live);
     let mut scraper = Facebook::new(
         "https://web.facebook.com/share/v/1BABvXc92K/",
         true,
-        client.clone(),
+        http,
     );
-    let (data, status) = scraper.get_data().await;
-    assert_eq!(status, 200);
+    let data = scraper.get_data().await.unwrap();
     println!("Data: {:#?}", data);
-}
\ No newline at end of file
+}
+
+#[test]
+fn facebook_fixtures() {
+    use super::snapshot::{assert_snapshot, fixture};
+    let data = Facebook::parse_html(&fixture("facebook/reel.html")).unwrap();
+    assert_snapshot("facebook/reel.full.json", &Facebook::extract(data.clone(), false).unwrap());
+    assert_snapshot("facebook/reel.cut.json", &Facebook::extract(data, true).unwrap());
+    let data = Facebook::parse_html(&fixture("facebook/reel_dash.html")).unwrap();
+    assert_snapshot("facebook/reel_dash.cut.json", &Facebook::extract(data, true).unwrap());
+    let data = Facebook::parse_html(&fixture("facebook/reel_sd.html")).unwrap();
+    let post = Facebook::cut(&data);
+    assert_eq!(post.media[0].address.as_deref(), string(data.get("browser_native_sd_url")).as_deref());
+    assert_snapshot("facebook/reel_sd.cut.json", &Facebook::extract(data, true).unwrap());
+}
+
+#[test]
+fn facebook_live_fixtures() {
+    use super::snapshot::{assert_snapshot, fixture};
+    let data = Facebook::parse_html(&fixture("facebook/live.html")).unwrap();
+    assert_snapshot("facebook/live.cut.json", &Facebook::extract(data, true).unwrap());
+    let data = Facebook::parse_html(&fixture("facebook/replay.html")).unwrap();
+    assert_snapshot("facebook/replay.cut.json", &Facebook::extract(data, true).unwrap());
+}
+
+#[test]
+fn facebook_picks_the_video_among_decoys() {
+    use super::snapshot::fixture;
+    let data = Facebook::parse_html(&fixture("facebook/reel_decoy.html")).unwrap();
+    assert_eq!(Facebook::cut(&data), Facebook::cut(&Facebook::parse_html(&fixture("facebook/reel_dash.html")).unwrap()));
+    let located = data["located"]["data"].as_str().unwrap();
+    assert!(located.contains("/require/1/"), "{}", located);
+
+    // A related video's player and story sit in an earlier script than ours.
+    let data = Facebook::parse_html(&fixture("facebook/reel_decoy_script.html")).unwrap();
+    assert_eq!(Facebook::cut(&data), Facebook::cut(&Facebook::parse_html(&fixture("facebook/reel_dash.html")).unwrap()));
+    assert!(data["located"]["video"].as_str().unwrap().starts_with("/2/"), "{}", data["located"]);
+}
+
+#[test]
+fn facebook_photo_fixtures() {
+    use super::snapshot::{assert_snapshot, fixture};
+    let html = fixture("facebook/photo.html");
+    let err = Facebook::parse_html(&html).unwrap_err();
+    assert!(matches!(err, ScrapeError::UpstreamChanged(_)));
+    let data = Facebook::parse_photos(&html).unwrap();
+    assert_snapshot("facebook/photo.cut.json", &Facebook::extract(data, true).unwrap());
+    let data = Facebook::parse_photos(&fixture("facebook/album.html")).unwrap();
+    assert_snapshot("facebook/album.cut.json", &Facebook::extract(data, true).unwrap());
+    assert!(Facebook::parse_photos(&fixture("facebook/reel.html")).is_none());
+    assert!(Facebook::matches("https://www.facebook.com/photo.php?fbid=10160000000000001"));
+    assert!(Facebook::matches("https://www.facebook.com/photo/?fbid=10160000000000001"));
+}
+
+#[test]
+fn facebook_comments_fixture() {
+    use super::snapshot::{assert_snapshot, fixture, fixture_json};
+    let feedback = Facebook::feedback(&fixture("facebook/reel.html")).unwrap();
+    let first = Facebook::cut_comments(&feedback["comment_rendering_instance_for_feed_location"]["comments"]);
+    assert_snapshot("facebook/comments.cut.json", &first);
+    let next = fixture_json("facebook/comments_page.json");
+    let next = Facebook::cut_comments(&next["data"]["node"]["comment_rendering_instance_for_feed_location"]["comments"]);
+    assert_eq!(next.items[0].text.as_deref(), Some("Saved this for later"));
+    assert!(!next.has_more);
+}
+
+#[test]
+fn facebook_story_fixture() {
+    use super::snapshot::{assert_snapshot, fixture};
+    let data = Facebook::parse_story(&fixture("facebook/story.html")).unwrap();
+    assert_snapshot("facebook/story.cut.json", &Facebook::extract(data, true).unwrap());
+    assert!(Facebook::parse_story(&fixture("facebook/reel.html")).is_none());
+}
+
+#[test]
+fn facebook_unavailable_fixture() {
+    let err = Facebook::parse_html(&super::snapshot::fixture("facebook/unavailable.html")).unwrap_err();
+    assert_eq!(err, ScrapeError::NotFound);
+}
@@ -0,0 +1,329 @@
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, COOKIE};
+use reqwest::redirect::Policy;
+use reqwest::{Client, Method};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use super::ScrapeError;
+
+/// Where responses come from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mode {
+    /// Straight to the network.
+    Live,
+    /// Hit the network and save every exchange as a cassette in the directory.
+    Record(PathBuf),
+    /// Never touch the network; answer from cassettes saved by `Record`.
+    Replay(PathBuf),
+}
+
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    /// Final URL after redirects.
+    pub url: String,
+    /// In the order received, with repeated headers such as `set-cookie` kept apart.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, ScrapeError> {
+        serde_json::from_slice(&self.body).map_err(|e| ScrapeError::UpstreamChanged(format!("Invalid JSON: {}", e)))
+    }
+
+    /// Every value of header `name`.
+    pub fn header_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers.iter()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The platform's HTTP status as an error, if it is not a success.
+    pub fn error(&self) -> Option<ScrapeError> {
+        ScrapeError::from_status(self.status)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Cassette {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    url: String,
+    body: Option<String>,
+}
+
+/// Text bodies are kept readable; anything else is stored as hex.
+#[derive(Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    url: String,
+    headers: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_hex: Option<String>,
+}
+
+impl From<&Response> for RecordedResponse {
+    fn from(r: &Response) -> Self {
+        let (body, body_hex) = match std::str::from_utf8(&r.body) {
+            Ok(text) => (Some(text.to_string()), None),
+            Err(_) => (None, Some(r.body.iter().map(|b| format!("{:02x}", b)).collect())),
+        };
+        let headers = r.headers.iter()
+            .map(|(k, v)| (k.clone(), if k.eq_ignore_ascii_case("set-cookie") { redact_cookie(v) } else { v.clone() }))
+            .collect();
+        Self { status: r.status, url: r.url.clone(), headers, body, body_hex }
+    }
+}
+
+/// A `set-cookie` value with the cookie's own value replaced, so recording against a
+/// logged-in session never writes credentials into a cassette. Name and attributes are kept.
+fn redact_cookie(cookie: &str) -> String {
+    let (pair, attributes) = cookie.split_once(';').map_or((cookie, None), |(p, a)| (p, Some(a)));
+    let name = pair.split_once('=').map_or(pair, |(name, _)| name);
+    match attributes {
+        Some(attributes) => format!("{}=redacted;{}", name, attributes),
+        None => format!("{}=redacted", name),
+    }
+}
+
+impl RecordedResponse {
+    fn into_response(self) -> Option<Response> {
+        let body = match (self.body, self.body_hex) {
+            (Some(text), _) => text.into_bytes(),
+            (None, Some(hex)) => (0..hex.len())
+                .step_by(2)
+                .map(|i| hex.get(i..i + 2).and_then(|h| u8::from_str_radix(h, 16).ok()))
+                .collect::<Option<Vec<u8>>>()?,
+            (None, None) => Vec::new(),
+        };
+        Some(Response { status: self.status, url: self.url, headers: self.headers, body })
+    }
+}
+
+/// The HTTP layer every scraper goes through, so a run can be recorded and replayed offline.
+#[derive(Clone)]
+pub struct Http {
+    client: Client,
+    /// Streams media without following redirects, so every hop can be checked against the
+    /// media hosts.
+    media: Client,
+    mode: Mode,
+    /// Session cookies by domain, e.g. a logged-in `sessionid` for instagram.com.
+    cookies: Vec<(String, String)>,
+}
+
+impl Http {
+    pub fn new(client: Client, mode: Mode) -> Self {
+        let media = Client::builder()
+            .redirect(Policy::none())
+            .build()
+            .expect("failed to build the media client");
+        Self { client, media, mode, cookies: vec![] }
+    }
+
+    /// Replaces the client used for streaming media, e.g. one resolving CDN hosts to a local
+    /// server. It must not follow redirects itself.
+    #[cfg(test)]
+    pub fn with_media_client(mut self, client: Client) -> Self {
+        self.media = client;
+        self
+    }
+
+    /// Keeps a logged-in `cookie` for `domain` and its subdomains. It is only sent on requests
+    /// whose headers went through `with_session`, so everything else stays anonymous.
+    pub fn with_cookie(mut self, domain: &str, cookie: &str) -> Self {
+        self.cookies.push((domain.to_string(), cookie.to_string()));
+        self
+    }
+
+    /// `headers` plus the session cookie kept for `url`'s domain, for the requests that need a
+    /// logged-in user such as stories. Headers that already carry a cookie are left alone.
+    pub fn with_session(&self, url: &str, mut headers: HeaderMap) -> HeaderMap {
+        if !headers.contains_key(COOKIE)
+            && let Some(cookie) = self.cookie_for(url).and_then(|c| HeaderValue::from_str(c).ok())
+        {
+            headers.insert(COOKIE, cookie);
+        }
+        headers
+    }
+
+    fn cookie_for(&self, url: &str) -> Option<&str> {
+        let host = reqwest::Url::parse(url).ok()?.host_str()?.to_string();
+        self.cookies.iter()
+            .find(|(domain, _)| host == *domain || host.ends_with(&format!(".{}", domain)))
+            .map(|(_, cookie)| cookie.as_str())
+    }
+
+    /// Raw client for streaming media, which is never recorded or replayed and never follows
+    /// redirects on its own.
+    pub fn media_client(&self) -> &Client {
+        &self.media
+    }
+
+    pub async fn get(&self, url: &str, headers: HeaderMap) -> Result<Response, ScrapeError> {
+        self.send(Request { method: Method::GET, url: url.to_string(), headers, body: None }).await
+    }
+
+    pub async fn post_form(&self, url: &str, headers: HeaderMap, form: &Value) -> Result<Response, ScrapeError> {
+        let body = form_urlencode(form);
+        self.send(Request { method: Method::POST, url: url.to_string(), headers, body: Some(body) }).await
+    }
+
+    pub async fn send(&self, request: Request) -> Result<Response, ScrapeError> {
+        match &self.mode {
+            Mode::Live => self.fetch(request).await,
+            Mode::Record(dir) => {
+                let recorded = RecordedRequest {
+                    method: request.method.to_string(),
+                    url: request.url.clone(),
+                    body: request.body.clone(),
+                };
+                let response = self.fetch(request).await?;
+                let file = dir.join(cassette_name(&recorded));
+                let cassette = Cassette { request: recorded, response: RecordedResponse::from(&response) };
+                let saved = serde_json::to_string_pretty(&cassette)
+                    .map_err(std::io::Error::from)
+                    .and_then(|json| std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&file, json)));
+                saved.map_err(|e| ScrapeError::Unavailable(format!("failed to record {}: {}", file.display(), e)))?;
+                Ok(response)
+            }
+            Mode::Replay(dir) => {
+                let recorded = RecordedRequest { method: request.method.to_string(), url: request.url, body: request.body };
+                load(dir, &recorded)
+            }
+        }
+    }
+
+    async fn fetch(&self, request: Request) -> Result<Response, ScrapeError> {
+        let mut headers = request.headers;
+        if request.body.is_some() && !headers.contains_key(CONTENT_TYPE) {
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/x-www-form-urlencoded"));
+        }
+        let mut builder = self.client.request(request.method, &request.url).headers(headers);
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+        let resp = builder.send().await?;
+        let status = resp.status().as_u16();
+        let url = resp.url().to_string();
+        let headers = resp.headers().iter()
+            .filter_map(|(k, v)| Some((k.as_str().to_string(), v.to_str().ok()?.to_string())))
+            .collect();
+        let body = resp.bytes().await?.to_vec();
+        Ok(Response { status, url, headers, body })
+    }
+}
+
+fn load(dir: &Path, request: &RecordedRequest) -> Result<Response, ScrapeError> {
+    let file = dir.join(cassette_name(request));
+    let text = std::fs::read_to_string(&file).map_err(|_| {
+        ScrapeError::NetworkError(format!("no cassette for {} {} ({})", request.method, request.url, file.display()))
+    })?;
+    serde_json::from_str::<Cassette>(&text)
+        .ok()
+        .and_then(|c| c.response.into_response())
+        .ok_or_else(|| ScrapeError::NetworkError(format!("bad cassette {}", file.display())))
+}
+
+/// Readable file name for a request: a slug of the URL plus a hash of everything that identifies it.
+fn cassette_name(request: &RecordedRequest) -> String {
+    let key = format!("{} {} {}", request.method, request.url, request.body.as_deref().unwrap_or(""));
+    let hash = key.bytes().fold(0xcbf29ce484222325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+    let slug: String = request.url
+        .split("://")
+        .last()
+        .unwrap_or("")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .take(80)
+        .collect();
+    format!("{}_{}-{:016x}.json", request.method, slug, hash)
+}
+
+fn form_urlencode(form: &Value) -> String {
+    let Some(map) = form.as_object() else { return String::new() };
+    map.iter()
+        .map(|(k, v)| {
+            let v = match v {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            format!("{}={}", encode(k), encode(&v))
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+pub fn encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn session_cookie_only_when_asked() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Echoes each request's headers back as the body.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut buf = [0u8; 4096];
+            let n = socket.read(&mut buf).await.unwrap_or(0);
+            let head = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+            let reply = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", head.len(), head);
+            let _ = socket.write_all(reply.as_bytes()).await;
+        }
+    });
+
+    let http = Http::new(Client::new(), Mode::Live).with_cookie("127.0.0.1", "sessionid=abc");
+    let url = format!("http://{}/", addr);
+    let anonymous = http.get(&url, HeaderMap::new()).await.unwrap().text();
+    assert!(!anonymous.contains("cookie:"), "{}", anonymous);
+    let logged_in = http.get(&url, http.with_session(&url, HeaderMap::new())).await.unwrap().text();
+    assert!(logged_in.contains("cookie: sessionid=abc"), "{}", logged_in);
+    assert!(!http.with_session("https://example.com/", HeaderMap::new()).contains_key(COOKIE));
+}
+
+#[test]
+fn recorded_cookies_are_redacted() {
+    let response = Response {
+        status: 200,
+        url: "https://www.instagram.com/".into(),
+        headers: vec![
+            ("set-cookie".into(), "sessionid=secret123; Path=/; HttpOnly".into()),
+            ("Set-Cookie".into(), "csrftoken=tok".into()),
+            ("content-type".into(), "text/html".into()),
+        ],
+        body: b"ok".to_vec(),
+    };
+    let recorded = RecordedResponse::from(&response);
+    assert_eq!(recorded.headers[0].1, "sessionid=redacted; Path=/; HttpOnly");
+    assert_eq!(recorded.headers[1].1, "csrftoken=redacted");
+    assert_eq!(recorded.headers[2].1, "text/html");
+}
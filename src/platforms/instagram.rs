@@ -1,15 +1,139 @@
-use reqwest::Client;
 use serde_json::{json, Value};
+use futures::future::BoxFuture;
+use regex::Regex;
+use reqwest::header::HeaderValue;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{Outcome, Platform, ScrapeError};
+use super::http::{Http, Response};
+use super::model::{boolean, number, string, Author, Comment, Content, MediaItem, MediaKind, Music, Page, Post, PostSummary, Profile, Scraped, Variant};
+
+/// First path segments that are Instagram pages rather than usernames.
+const RESERVED: [&str; 12] = ["p", "reel", "reels", "tv", "stories", "explore", "accounts", "direct", "about", "developer", "legal", "api"];
+
+/// Values the web app embeds in its pages and sends back with every GraphQL query. Instagram
+/// rotates them, so they are refreshed from instagram.com and cached for a while.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tokens {
+    pub doc_id: String,
+    pub lsd: String,
+    pub rev: String,
+    pub spin_t: String,
+    pub csrf: String,
+    /// Persisted query for a user's timeline, paged with `first`/`after`. Configured only:
+    /// the home page exports Relay doc ids, not these md5 hashes.
+    pub timeline_hash: String,
+    /// Persisted query for a post's top-level comments, paged with `first`/`after`. Configured only.
+    pub comments_hash: String,
+}
+
+impl Default for Tokens {
+    fn default() -> Self {
+        Self {
+            doc_id: "9510064595728286".to_string(),
+            lsd: "AVqQ3As1H7g".to_string(),
+            rev: "1023049274".to_string(),
+            spin_t: "1747835843".to_string(),
+            csrf: "11111111111".to_string(),
+            timeline_hash: "69cba40317214236af40e7efa697781d".to_string(),
+            comments_hash: "97b41c52301f77ce508f55e66d17620e".to_string(),
+        }
+    }
+}
+
+impl Tokens {
+    /// `base` with every token found in an instagram.com page swapped in.
+    pub fn scrape(html: &str, base: &Tokens) -> Tokens {
+        let find = |pattern: &str| Regex::new(pattern).unwrap().captures(html).map(|cap| cap[1].to_string());
+        Tokens {
+            doc_id: find(r#"PolarisPostActionLoadPostQueryQuery_instagramRelayOperation",\[\],\(?function\([^)]*\)\{e\.exports="(\d+)""#)
+                .unwrap_or_else(|| base.doc_id.clone()),
+            lsd: find(r#"\["LSD",\[\],\{"token":"([^"]+)""#).unwrap_or_else(|| base.lsd.clone()),
+            rev: find(r#""server_revision":(\d+)"#).or_else(|| find(r#""__spin_r":(\d+)"#)).unwrap_or_else(|| base.rev.clone()),
+            spin_t: find(r#""__spin_t":(\d+)"#).unwrap_or_else(|| base.spin_t.clone()),
+            csrf: find(r#""csrf_token":"([^"]+)""#).unwrap_or_else(|| base.csrf.clone()),
+            timeline_hash: base.timeline_hash.clone(),
+            comments_hash: base.comments_hash.clone(),
+        }
+    }
+
+    /// The checksum the web app sends next to `lsd`.
+    fn jazoest(&self) -> String {
+        format!("2{}", self.lsd.bytes().map(|b| b as u32).sum::<u32>())
+    }
+}
+
+struct TokenStore {
+    base: Tokens,
+    ttl: Duration,
+    cached: Option<(Instant, Tokens)>,
+}
+
+/// Tokens bootstrapped once and shared by every scraper that wasn't handed its own store.
+static TOKENS: LazyLock<Arc<Mutex<TokenStore>>> = LazyLock::new(|| {
+    Arc::new(Mutex::new(TokenStore { base: Tokens::default(), ttl: Duration::from_secs(3600), cached: None }))
+});
+
+/// Sets the tokens to start from (and fall back to when bootstrapping fails) and how long
+/// bootstrapped tokens are trusted.
+pub fn configure(base: Tokens, ttl: Duration) {
+    let mut store = TOKENS.lock().unwrap();
+    *store = TokenStore { base, ttl, cached: None };
+}
+
+/// What an Instagram URL points at.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    /// `/p/`, `/reel/` or `/tv/` shortcode.
+    Post(String),
+    /// `/stories/<username>/` or `/stories/<username>/<id>`; the user's whole current story is
+    /// returned either way, not just the frame the id points at.
+    Story(String),
+    /// `/stories/highlights/<id>`.
+    Highlight(String),
+    /// `/<username>/`.
+    Profile(String),
+}
+
+impl Target {
+    pub fn parse(url: &str) -> Option<Self> {
+        let highlight = Regex::new(r"instagram\.com/stories/highlights/(\d+)").unwrap();
+        let story = Regex::new(r"instagram\.com/stories/([A-Za-z0-9._]+)").unwrap();
+        if let Some(cap) = highlight.captures(url) {
+            return Some(Self::Highlight(cap[1].to_string()));
+        }
+        if let Some(cap) = story.captures(url) {
+            return Some(Self::Story(cap[1].to_string()));
+        }
+        if let Some(cap) = Instagram::pattern().captures(url) {
+            return Some(Self::Post(cap[2].to_string()));
+        }
+        let profile = Regex::new(r"instagram\.com/([A-Za-z0-9._]+)/?(?:[?#].*)?$").unwrap();
+        profile.captures(url)
+            .map(|cap| cap[1].to_string())
+            .filter(|name| !RESERVED.contains(&name.as_str()))
+            .map(Self::Profile)
+    }
+}
 
 pub struct Instagram {
-    client: Client,
-    item_id: String,
+    http: Http,
+    target: Target,
     cut: bool,
+    tokens: Arc<Mutex<TokenStore>>,
 }
 
 impl Instagram {
-    pub fn new(client: Client, item_id: String, cut: bool) -> Self {
-        Self { client, item_id, cut }
+    pub fn new(http: Http, target: Target, cut: bool) -> Self {
+        Self { http, target, cut, tokens: TOKENS.clone() }
+    }
+
+    /// Replaces the shared token store, so a test can seed its own tokens without racing others.
+    #[cfg(test)]
+    fn with_tokens(mut self, tokens: Arc<Mutex<TokenStore>>) -> Self {
+        self.tokens = tokens;
+        self
     }
 
     fn graphql() -> &'static str {
@@ -27,11 +151,319 @@ impl Instagram {
         ));
         headers.insert("Origin", HeaderValue::from_static("https://www.instagram.com"));
         headers.insert("Referer", HeaderValue::from_static("https://www.instagram.com"));
-        headers.insert("X-CSRFToken", HeaderValue::from_static("11111111111"));
         headers
     }
 
-    pub async fn get_data(&self) -> (Value, u16) {
+    /// Headers for the `/api/v1/` endpoints the web app calls, which want its app id.
+    fn api_headers() -> reqwest::header::HeaderMap {
+        let mut headers = Self::headers();
+        headers.remove(reqwest::header::CONTENT_TYPE);
+        headers.insert("X-IG-App-ID", reqwest::header::HeaderValue::from_static("936619743392459"));
+        headers
+    }
+
+    /// Calls an `/api/v1/` endpoint, with the session cookie when `session` is set.
+    async fn api(&self, path: &str, session: bool) -> Result<Value, ScrapeError> {
+        let url = format!("https://www.instagram.com/api/v1/{}", path);
+        let headers = match session {
+            true => self.http.with_session(&url, Self::api_headers()),
+            false => Self::api_headers(),
+        };
+        let resp = self.http.get(&url, headers).await?;
+        if resp.url.contains("/accounts/login") {
+            return Err(ScrapeError::LoginRequired);
+        }
+        if let Some(e) = resp.error() {
+            return Err(e);
+        }
+        let data: Value = resp.json()?;
+        Self::check_response(&data)?;
+        Ok(data)
+    }
+
+    /// `web_profile_info` for `username`, i.e. the `data.user` object.
+    async fn profile_info(&self, username: &str) -> Result<Value, ScrapeError> {
+        let data = self.api(&format!("users/web_profile_info/?username={}", super::http::encode(username)), false).await?;
+        data.get("data").and_then(|d| d.get("user"))
+            .filter(|u| !u.is_null())
+            .cloned()
+            .ok_or(ScrapeError::NotFound)
+    }
+
+    /// A story's reel id is its owner's user id.
+    async fn story_reel_id(&self, username: &str) -> Result<String, ScrapeError> {
+        let user = self.profile_info(username).await?;
+        string(user.get("id")).ok_or_else(|| ScrapeError::UpstreamChanged("profile has no id".into()))
+    }
+
+    /// Every frame of reel `reel_id`: a user id for stories, `highlight:<id>` for highlights.
+    async fn get_reel(&self, reel_id: &str) -> Outcome {
+        let data = self.api(&format!("feed/reels_media/?reel_ids={}", super::http::encode(reel_id)), true).await?;
+        Self::extract_reel(data, self.cut)
+    }
+
+    pub async fn get_data(&self) -> Outcome {
+        match &self.target {
+            Target::Post(shortcode) => self.get_post(shortcode).await,
+            Target::Story(username) => self.get_reel(&self.story_reel_id(username).await?).await,
+            Target::Highlight(id) => self.get_reel(&format!("highlight:{}", id)).await,
+            Target::Profile(_) => Err(ScrapeError::BadRequest("profiles are served by /api/profile".into())),
+        }
+    }
+
+    /// The profile plus its first posts from `web_profile_info`, or the page after `cursor`
+    /// from the timeline query.
+    pub async fn get_profile(&self, cursor: Option<String>) -> Result<Profile, ScrapeError> {
+        let Target::Profile(username) = &self.target else {
+            return Err(ScrapeError::BadRequest("not a profile URL".into()));
+        };
+        let user = self.profile_info(username).await?;
+        let Some(cursor) = cursor else {
+            return Ok(Self::cut_profile(&user, None));
+        };
+
+        let id = string(user.get("id")).ok_or_else(|| ScrapeError::UpstreamChanged("profile has no id".into()))?;
+        let data = self.query(|t| &t.timeline_hash, json!({ "id": id, "first": 12, "after": cursor })).await?;
+        let timeline = data.pointer("/data/user/edge_owner_to_timeline_media")
+            .ok_or_else(|| ScrapeError::UpstreamChanged("no timeline in response".into()))?;
+        Ok(Self::cut_profile(&user, Some(timeline)))
+    }
+
+    /// Runs a persisted GraphQL query by the hash `hash` picks from the tokens, the way the web
+    /// app pages through lists. The hashes are configured only, so they are read from the
+    /// store's base without bootstrapping.
+    async fn query(&self, hash: fn(&Tokens) -> &String, variables: Value) -> Result<Value, ScrapeError> {
+        let variables = super::http::encode(&variables.to_string());
+        let hash = hash(&self.tokens.lock().unwrap().base).clone();
+        let url = format!("{}?query_hash={}&variables={}", Self::graphql(), hash, variables);
+        let resp = self.http.get(&url, Self::api_headers()).await?;
+        if let Some(e) = resp.error() {
+            return Err(e);
+        }
+        let data: Value = resp.json()?;
+        Self::check_response(&data)?;
+        Ok(data)
+    }
+
+    /// A page of top-level comments with the replies Instagram sends along. The first page comes
+    /// with the post itself; later ones from the comments query.
+    pub async fn get_comments(&self, cursor: Option<String>) -> Result<Page<Comment>, ScrapeError> {
+        let Target::Post(shortcode) = &self.target else {
+            return Err(ScrapeError::BadRequest("comments need a post URL".into()));
+        };
+        let comments = match cursor {
+            None => {
+                let data = self.post_query(shortcode).await?;
+                Self::check_response(&data)?;
+                data.pointer("/data/xdt_shortcode_media")
+                    .filter(|m| !m.is_null())
+                    .ok_or(ScrapeError::NotFound)?
+                    .get("edge_media_to_parent_comment")
+                    .cloned()
+            }
+            Some(cursor) => {
+                let data = self.query(|t| &t.comments_hash, json!({ "shortcode": shortcode, "first": 24, "after": cursor })).await?;
+                data.pointer("/data/shortcode_media/edge_media_to_parent_comment").cloned()
+            }
+        };
+        let comments = comments.ok_or_else(|| ScrapeError::UpstreamChanged("no comments in response".into()))?;
+        Ok(Self::cut_comments(&comments))
+    }
+
+    /// Reads an `edge_media_to_parent_comment` (or `edge_threaded_comments`) connection.
+    pub fn cut_comments(edge: &Value) -> Page<Comment> {
+        let edges = edge.get("edges").and_then(|e| e.as_array()).cloned().unwrap_or_default();
+        Page {
+            items: edges.iter().filter_map(|e| e.get("node")).map(|node| {
+                let replies = node.get("edge_threaded_comments");
+                Comment {
+                    id: string(node.get("id")),
+                    text: string(node.get("text")),
+                    author: Self::author(node.get("owner")),
+                    likes: number(node.pointer("/edge_liked_by/count")),
+                    timestamp: number(node.get("created_at")),
+                    reply_count: number(replies.and_then(|r| r.get("count"))),
+                    replies: replies.map(|r| Self::cut_comments(r).items).unwrap_or_default(),
+                }
+            }).collect(),
+            cursor: string(edge.pointer("/page_info/end_cursor")),
+            has_more: boolean(edge.pointer("/page_info/has_next_page")).unwrap_or(false),
+        }
+    }
+
+    /// Builds the profile from a `web_profile_info` user and, when paging, a later timeline page.
+    pub fn cut_profile(user: &Value, timeline: Option<&Value>) -> Profile {
+        let timeline = timeline.or_else(|| user.get("edge_owner_to_timeline_media"));
+        let page_info = timeline.and_then(|t| t.get("page_info"));
+        let edges = timeline.and_then(|t| t.get("edges")).and_then(|e| e.as_array()).cloned().unwrap_or_default();
+        let items = edges.iter().filter_map(|e| e.get("node")).map(|node| {
+            let shortcode = string(node.get("shortcode"));
+            PostSummary {
+                id: string(node.get("id")),
+                url: shortcode.as_ref().map(|s| format!("https://www.instagram.com/p/{}/", s)),
+                shortcode,
+                kind: if boolean(node.get("is_video")) == Some(true) { MediaKind::Video } else { MediaKind::Image },
+                cover: string(node.get("thumbnail_src")).or_else(|| string(node.get("display_url"))),
+                desc: string(node.pointer("/edge_media_to_caption/edges/0/node/text")),
+                views: number(node.get("video_view_count")),
+                likes: number(node.pointer("/edge_liked_by/count")).or_else(|| number(node.pointer("/edge_media_preview_like/count"))),
+                comments: number(node.pointer("/edge_media_to_comment/count")),
+                timestamp: number(node.get("taken_at_timestamp")),
+            }
+        }).collect();
+
+        Profile {
+            platform: "instagram".to_string(),
+            author: Self::author(Some(user)),
+            posts: Page {
+                items,
+                cursor: string(page_info.and_then(|p| p.get("end_cursor"))),
+                has_more: boolean(page_info.and_then(|p| p.get("has_next_page"))).unwrap_or(false),
+            },
+        }
+    }
+
+    /// Author from a GraphQL user, either a post's `owner` or a full profile.
+    fn author(user: Option<&Value>) -> Author {
+        let field = |key: &str| user.and_then(|u| u.get(key));
+        Author {
+            id: string(field("id")),
+            name: string(field("full_name")),
+            username: string(field("username")),
+            verified: boolean(field("is_verified")),
+            image: string(field("profile_pic_url_hd")).or_else(|| string(field("profile_pic_url"))),
+            bio: string(field("biography")),
+            posts: number(field("edge_owner_to_timeline_media").and_then(|v| v.get("count"))),
+            followers: number(field("edge_followed_by").and_then(|f| f.get("count"))),
+            following: number(field("edge_follow").and_then(|f| f.get("count"))),
+            private: boolean(field("is_private")),
+            website: string(field("external_url")),
+            ..Default::default()
+        }
+    }
+
+    /// A `reels_media` response: the frames of one story or highlight.
+    pub fn extract_reel(mut data: Value, cut: bool) -> Outcome {
+        Self::check_response(&data)?;
+        data["platform"] = json!("instagram");
+
+        let reel = data.get("reels_media").and_then(|r| r.as_array()).and_then(|r| r.first())
+            .or_else(|| data.get("reels").and_then(|r| r.as_object()).and_then(|r| r.values().next()))
+            .filter(|r| r.get("items").and_then(|i| i.as_array()).is_some_and(|i| !i.is_empty()))
+            .ok_or(ScrapeError::NotFound)?;
+
+        if !cut {
+            return Ok(Scraped::Full(data));
+        }
+
+        Ok(Scraped::Cut(Box::new(Self::cut_reel(reel))))
+    }
+
+    fn candidates(list: Option<&Value>) -> Vec<Variant> {
+        list.and_then(|l| l.as_array()).cloned().unwrap_or_default().iter()
+            .filter_map(|c| {
+                Some(Variant {
+                    address: string(c.get("url"))?,
+                    width: number(c.get("width")),
+                    height: number(c.get("height")),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    /// One story frame from the `/api/v1/` item shape.
+    fn frame(item: &Value) -> MediaItem {
+        let images = Self::candidates(item.get("image_versions2").and_then(|i| i.get("candidates")));
+        let videos = Self::candidates(item.get("video_versions"));
+        let cover = images.first().map(|v| v.address.clone());
+        let (kind, variants) = match videos.is_empty() {
+            true => (MediaKind::Image, images),
+            false => (MediaKind::Video, videos),
+        };
+        MediaItem {
+            id: string(item.get("pk")).or_else(|| string(item.get("id"))),
+            kind,
+            address: variants.first().map(|v| v.address.clone()),
+            cover,
+            width: number(item.get("original_width")),
+            height: number(item.get("original_height")),
+            timestamp: number(item.get("taken_at")),
+            expires_at: number(item.get("expiring_at")),
+            variants,
+            ..Default::default()
+        }
+    }
+
+    pub fn cut_reel(reel: &Value) -> Post {
+        let user = reel.get("user");
+        let items = reel.get("items").and_then(|i| i.as_array()).cloned().unwrap_or_default();
+        Post {
+            platform: "instagram".to_string(),
+            content: Content {
+                id: string(reel.get("id")),
+                title: string(reel.get("title")),
+                cover: string(reel.get("cover_media").and_then(|c| c.get("cropped_image_version")).and_then(|c| c.get("url"))),
+                ..Default::default()
+            },
+            author: Author {
+                id: string(user.and_then(|u| u.get("pk"))).or_else(|| string(user.and_then(|u| u.get("id")))),
+                name: string(user.and_then(|u| u.get("full_name"))),
+                username: string(user.and_then(|u| u.get("username"))),
+                verified: boolean(user.and_then(|u| u.get("is_verified"))),
+                image: string(user.and_then(|u| u.get("profile_pic_url"))),
+                ..Default::default()
+            },
+            media: items.iter().map(Self::frame).collect(),
+            music: None,
+        }
+    }
+
+    fn pattern() -> Regex {
+        Regex::new(r"instagram\.com/(p|reel|tv)/([A-Za-z0-9_-]+)/?").unwrap()
+    }
+
+    /// Cached tokens, bootstrapped from the instagram.com home page when missing, expired or
+    /// `refresh`ed. If the page can't be read the configured tokens are used, without caching
+    /// them, so the next request tries again.
+    async fn tokens(&self, refresh: bool) -> Tokens {
+        let base = {
+            let store = self.tokens.lock().unwrap();
+            if !refresh
+                && let Some((at, tokens)) = &store.cached
+                && at.elapsed() < store.ttl
+            {
+                return tokens.clone();
+            }
+            store.base.clone()
+        };
+
+        let mut headers = Self::headers();
+        headers.remove(reqwest::header::CONTENT_TYPE);
+        let resp = match self.http.get("https://www.instagram.com/", headers).await {
+            Ok(resp) if resp.error().is_none() => resp,
+            _ => return base,
+        };
+        let mut tokens = Tokens::scrape(&resp.text(), &base);
+        let csrf = Regex::new(r"^\s*csrftoken=([^;]+)").unwrap();
+        if let Some(cap) = resp.header_values("set-cookie").find_map(|cookie| csrf.captures(cookie)) {
+            tokens.csrf = cap[1].to_string();
+        }
+        self.tokens.lock().unwrap().cached = Some((Instant::now(), tokens.clone()));
+        tokens
+    }
+
+    /// Stale tokens get a 400/403 or a GraphQL `errors` list instead of data.
+    fn tokens_rejected(resp: &Response) -> bool {
+        if matches!(resp.status, 400 | 403) {
+            return true;
+        }
+        resp.json::<Value>()
+            .map(|data| data.get("errors").is_some() && data.get("data").is_none_or(|d| d.is_null()))
+            .unwrap_or(false)
+    }
+
+    async fn send_post_query(&self, shortcode: &str, tokens: &Tokens) -> Result<Response, ScrapeError> {
         let graphql_data = json!({
             "av": "0",
             "__d": "www",
@@ -41,142 +473,394 @@ impl Instagram {
             "__hs": "20229.HYP:instagram_web_pkg.2.1...0",
             "dpr": "1",
             "__ccg": "GOOD",
-            "__rev": "1023049274",
+            "__rev": tokens.rev,
             "__comet_req": "7",
-            "lsd": "AVqQ3As1H7g",
-            "jazoest": "2855",
-            "__spin_r": "1023049274",
+            "lsd": tokens.lsd,
+            "jazoest": tokens.jazoest(),
+            "__spin_r": tokens.rev,
             "__spin_b": "trunk",
-            "__spin_t": "1747835843",
+            "__spin_t": tokens.spin_t,
             "fb_api_caller_class": "RelayModern",
             "fb_api_req_friendly_name": "PolarisPostActionLoadPostQueryQuery",
-            "variables": format!("{{\"shortcode\":\"{}\",\"fetch_tagged_user_count\":null,\"hoisted_comment_id\":null,\"hoisted_reply_id\":null}}", self.item_id),
+            "variables": format!("{{\"shortcode\":\"{}\",\"fetch_tagged_user_count\":null,\"hoisted_comment_id\":null,\"hoisted_reply_id\":null}}", shortcode),
             "server_timestamps": "true",
-            "doc_id": "9510064595728286"
+            "doc_id": tokens.doc_id
         });
 
-        let resp = match self.client.post(Self::graphql())
-            .headers(Self::headers())
-            .form(&graphql_data)
-            .send()
-            .await
-        {
-            Ok(r) => r,
-            Err(e) => {
-                return (json!({ "error_message": format!("Request failed: {}", e) }), 502);
-            }
-        };
+        self.http.post_form(Self::graphql(), Self::query_headers(tokens), &graphql_data).await
+    }
 
-        let mut data: Value = match resp.json().await {
-            Ok(d) => d,
-            Err(e) => {
-                return (json!({ "error_message": format!("JSON parse failed: {}", e) }), 502);
+    /// Headers for the post query. Instagram checks `X-CSRFToken` against the `csrftoken`
+    /// cookie, so the token goes out as both.
+    fn query_headers(tokens: &Tokens) -> reqwest::header::HeaderMap {
+        let mut headers = Self::headers();
+        let cookie = format!("csrftoken={}", tokens.csrf);
+        for (name, value) in [("X-CSRFToken", &tokens.csrf), ("X-FB-LSD", &tokens.lsd), ("Cookie", &cookie)] {
+            if let Ok(value) = HeaderValue::from_str(value) {
+                headers.insert(name, value);
             }
-        };
+        }
+        headers
+    }
+
+    /// `PolarisPostActionLoadPostQueryQuery`: the post, its owner and the first comments.
+    /// Retried once with freshly bootstrapped tokens if Instagram rejects the cached ones.
+    async fn post_query(&self, shortcode: &str) -> Result<Value, ScrapeError> {
+        let mut resp = self.send_post_query(shortcode, &self.tokens(false).await).await?;
+        if Self::tokens_rejected(&resp) {
+            resp = self.send_post_query(shortcode, &self.tokens(true).await).await?;
+        }
+        if let Some(e) = resp.error() {
+            return Err(e);
+        }
+
+        resp.json()
+    }
+
+    async fn get_post(&self, shortcode: &str) -> Outcome {
+        let data = self.post_query(shortcode).await?;
+        Self::extract(data, self.cut)
+    }
+
+    pub fn extract(mut data: Value, cut: bool) -> Outcome {
+        Self::check_response(&data)?;
 
         data["platform"] = json!("instagram");
 
-        if !self.cut {
-            return (data, 200);
+        let item = data.get("data").and_then(|d| d.get("xdt_shortcode_media"))
+            .filter(|i| !i.is_null())
+            .ok_or(ScrapeError::NotFound)?;
+
+        if !cut {
+            return Ok(Scraped::Full(data));
         }
 
-        let item = match data.get("data").and_then(|d| d.get("xdt_shortcode_media")) {
-            Some(i) => i,
-            None => return (json!({ "error_message": "Item not found" }), 502),
+        Ok(Scraped::Cut(Box::new(Self::cut(item))))
+    }
+
+    /// GraphQL failures come back as `{"status": "fail", "message": ...}` with a 200.
+    fn check_response(data: &Value) -> Result<(), ScrapeError> {
+        if data.get("require_login").and_then(|r| r.as_bool()).unwrap_or(false) {
+            return Err(ScrapeError::LoginRequired);
+        }
+        if data.get("status").and_then(|s| s.as_str()) == Some("fail") {
+            let message = data.get("message").and_then(|m| m.as_str()).unwrap_or("");
+            if message.contains("wait a few minutes") {
+                return Err(ScrapeError::RateLimited);
+            }
+            return Err(ScrapeError::UpstreamChanged(message.to_string()));
+        }
+        Ok(())
+    }
+
+    /// `display_resources` as variants, largest first.
+    fn resources(node: &Value) -> Vec<Variant> {
+        let mut resources: Vec<Variant> = node.get("display_resources").and_then(|r| r.as_array()).cloned().unwrap_or_default()
+            .iter()
+            .filter_map(|r| {
+                let (width, height) = (number(r.get("config_width")), number(r.get("config_height")));
+                Some(Variant {
+                    name: width.zip(height).map(|(w, h)| format!("{}x{}", w, h)),
+                    address: string(r.get("src"))?,
+                    width,
+                    height,
+                    ..Default::default()
+                })
+            })
+            .collect();
+        resources.sort_by_key(|v| std::cmp::Reverse(v.width));
+        resources
+    }
+
+    /// One image or video, from a carousel child or the post itself. Images list their size
+    /// ladder as `variants`; videos list it as `covers`.
+    fn media_item(node: &Value) -> MediaItem {
+        let resources = Self::resources(node);
+        let display = string(node.get("display_url"));
+        let mut media_item = MediaItem {
+            id: string(node.get("id")),
+            kind: MediaKind::Image,
+            address: resources.first().map(|v| v.address.clone()).or_else(|| display.clone()),
+            cover: display,
+            alt: string(node.get("accessibility_caption")),
+            width: number(node.pointer("/dimensions/width")),
+            height: number(node.pointer("/dimensions/height")),
+            ..Default::default()
         };
+        match string(node.get("video_url")) {
+            Some(video) => {
+                media_item.kind = MediaKind::Video;
+                media_item.address = Some(video);
+                media_item.has_audio = boolean(node.get("has_audio"));
+                media_item.plays = number(node.get("video_play_count"));
+                media_item.views = number(node.get("video_view_count"));
+                media_item.covers = resources;
+            }
+            None => media_item.variants = resources,
+        }
+        media_item
+    }
 
+    fn last_resource(item: &Value) -> Option<String> {
+        string(item.get("display_resources").and_then(|r| r.as_array()).and_then(|r| r.last()).and_then(|l| l.get("src")))
+    }
+
+    pub fn cut(item: &Value) -> Post {
         let desc = item.get("edge_media_to_caption")
             .and_then(|c| c.get("edges"))
             .and_then(|e| e.as_array())
-            .and_then(|arr| arr.get(0))
+            .and_then(|arr| arr.first())
             .and_then(|n| n.get("node"))
-            .and_then(|n| n.get("text"))
-            .cloned()
-            .unwrap_or(json!("no desc"));
-
-        let mut content = json!({
-            "id": item.get("id").cloned().unwrap_or(json!("N/A")),
-            "shortcode": item.get("shortcode").cloned().unwrap_or(json!("N/A")),
-            "likes": item.get("edge_media_preview_like").and_then(|l| l.get("count")).cloned().unwrap_or(json!(0)),
-            "desc": desc,
-            "cover": item.get("thumbnail_src").cloned().unwrap_or(json!("N/A")),
-            "is_video": item.get("is_video").cloned().unwrap_or(json!(false))
-        });
+            .and_then(|n| n.get("text"));
 
-        if let Some(resources) = item.get("display_resources").and_then(|r| r.as_array()) {
-            if let Some(last) = resources.last() {
-                content["cover"] = last.get("src").cloned().unwrap_or(json!("N/A"));
-            }
+        let content = Content {
+            id: string(item.get("id")),
+            shortcode: string(item.get("shortcode")),
+            likes: number(item.get("edge_media_preview_like").and_then(|l| l.get("count"))),
+            comments: number(item.get("edge_media_to_parent_comment").and_then(|c| c.get("count"))),
+            desc: string(desc),
+            cover: Self::last_resource(item).or_else(|| string(item.get("thumbnail_src"))),
+            views: number(item.get("video_view_count")),
+            plays: number(item.get("video_play_count")),
+            ..Default::default()
+        };
+
+        let author = Self::author(item.get("owner"));
+
+        let media = match item.pointer("/edge_sidecar_to_children/edges").and_then(|a| a.as_array()) {
+            Some(edges) => edges.iter().filter_map(|edge| edge.get("node")).map(Self::media_item).collect(),
+            None => vec![Self::media_item(item)],
+        };
+
+        Post {
+            platform: "instagram".to_string(),
+            content,
+            author,
+            media,
+            music: Self::music(item),
         }
+    }
 
-        let author = json!({
-            "name": item.get("owner").and_then(|o| o.get("full_name")).cloned().unwrap_or(json!("N/A")),
-            "username": item.get("owner").and_then(|o| o.get("username")).cloned().unwrap_or(json!("N/A")),
-            "verified": item.get("owner").and_then(|o| o.get("is_verified")).cloned().unwrap_or(json!(false)),
-            "image": item.get("owner").and_then(|o| o.get("profile_pic_url")).cloned().unwrap_or(json!("N/A")),
-            "videos": item.get("owner").and_then(|o| o.get("edge_owner_to_timeline_media")).and_then(|v| v.get("count")).cloned().unwrap_or(json!(0)),
-            "followers": item.get("owner").and_then(|o| o.get("edge_followed_by")).and_then(|f| f.get("count")).cloned().unwrap_or(json!(0))
-        });
+    /// Reel sound from `clips_music_attribution_info`. The audio file itself only comes with
+    /// `clips_metadata`, which not every response carries.
+    fn music(item: &Value) -> Option<Music> {
+        let attribution = item.get("clips_music_attribution_info").filter(|a| !a.is_null());
+        let clips = item.get("clips_metadata");
+        let licensed = clips.and_then(|c| c.get("music_info")).filter(|m| !m.is_null());
+        let original = clips.and_then(|c| c.get("original_sound_info")).filter(|o| !o.is_null());
+        if attribution.is_none() && licensed.is_none() && original.is_none() {
+            return None;
+        }
+        let asset = licensed.and_then(|m| m.get("music_asset_info"));
+        let field = |key: &str| attribution.and_then(|a| a.get(key));
+        Some(Music {
+            id: string(field("audio_id"))
+                .or_else(|| string(asset.and_then(|a| a.get("audio_cluster_id"))))
+                .or_else(|| string(original.and_then(|o| o.get("audio_asset_id")))),
+            title: string(field("song_name"))
+                .or_else(|| string(asset.and_then(|a| a.get("title"))))
+                .or_else(|| string(original.and_then(|o| o.get("original_audio_title")))),
+            author: string(field("artist_name"))
+                .or_else(|| string(asset.and_then(|a| a.get("display_artist"))))
+                .or_else(|| string(original.and_then(|o| o.pointer("/ig_artist/username")))),
+            cover: string(asset.and_then(|a| a.get("cover_artwork_uri"))),
+            duration: number(asset.or(original).and_then(|a| a.get("duration_in_ms"))).map(|ms| ms / 1000),
+            original: boolean(field("uses_original_audio")).or(Some(licensed.is_none())),
+            src: string(asset.or(original).and_then(|a| a.get("progressive_download_url"))),
+        })
+    }
+}
 
-        let mut media = vec![];
-        if let Some(edges) = item.get("edge_sidecar_to_children")
-            .and_then(|c| c.get("edges"))
-            .and_then(|a| a.as_array()) 
-        {
-            for edge in edges {
-                if let Some(node) = edge.get("node") {
-                    let mut media_item = json!({
-                        "id": node.get("id").cloned().unwrap_or(json!("N/A")),
-                        "shortcode": node.get("shortcode").cloned().unwrap_or(json!("N/A")),
-                        "address": node.get("display_url").cloned().unwrap_or(json!("N/A")),
-                        "cover": node.get("display_url").cloned().unwrap_or(json!("N/A")),
-                        "is_video": node.get("video_url").is_some()
-                    });
-                    if let Some(resources) = node.get("display_resources").and_then(|r| r.as_array()) {
-                        if let Some(last) = resources.last() {
-                            media_item["address"] = last.get("src").cloned().unwrap_or(media_item["address"].clone());
-                        }
-                    }
-                    if let Some(video) = node.get("video_url") {
-                        media_item["address"] = video.clone();
-                        media_item["play"] = node.get("video_play_count").cloned().unwrap_or(json!(0));
-                        media_item["views"] = node.get("video_view_count").cloned().unwrap_or(json!(0));
-                    }
-                    media.push(media_item);
-                }
-            }
-        } else if let Some(video_url) = item.get("video_url") {
-            media.push(json!({
-                "id": item.get("id").cloned().unwrap_or(json!("N/A")),
-                "shortcode": item.get("shortcode").cloned().unwrap_or(json!("N/A")),
-                "address": video_url.clone(),
-                "is_video": item.get("is_video").cloned().unwrap_or(json!(false)),
-                "cover": item.get("display_url").cloned().unwrap_or(json!("N/A"))
-            }));
-        }
-
-        let result = json!({
-            "platform": "instagram",
-            "content": content,
-            "author": author,
-            "media": media
-        });
+impl Platform for Instagram {
+    fn matches(url: &str) -> bool {
+        Target::parse(url).is_some()
+    }
+
+    fn name() -> &'static str {
+        "instagram"
+    }
 
-        (result, 200)
+    fn from_url(url: &str, cut: bool, http: Http) -> Self {
+        let target = Target::parse(url).unwrap_or(Target::Post(String::new()));
+        Self::new(http, target, cut)
     }
 
+    fn media_hosts() -> &'static [&'static str] {
+        &["cdninstagram.com", "fbcdn.net"]
+    }
 
+    fn media_headers() -> reqwest::header::HeaderMap {
+        Self::headers()
+    }
+
+    fn fetch(&mut self) -> BoxFuture<'_, Outcome> {
+        Box::pin(self.get_data())
+    }
+
+    fn profile(&mut self, cursor: Option<String>) -> BoxFuture<'_, Result<Profile, ScrapeError>> {
+        Box::pin(self.get_profile(cursor))
+    }
+
+    fn comments(&mut self, cursor: Option<String>) -> BoxFuture<'_, Result<Page<Comment>, ScrapeError>> {
+        Box::pin(self.get_comments(cursor))
+    }
 }
 
 #[tokio::test]
+#[ignore = "hits the live site"]
 async fn instagram() {
-    let client = reqwest::Client::new();
-    let scraper = Instagram { 
-        client,
-        item_id: "DHm7knuzl1D".to_string(),
+    let scraper = Instagram {
+        http: Http::new(reqwest::Client::new(), super::http::Mode::Live),
+        target: Target::Post("DHm7knuzl1D".to_string()),
         cut: true,
+        tokens: TOKENS.clone(),
     };
-    let (data, status) = scraper.get_data().await;
-    assert_eq!(status, 200);
+    let data = scraper.get_data().await.unwrap();
     println!("Data: {:#?}", data);
 }
+
+#[test]
+fn instagram_fixtures() {
+    use super::snapshot::{assert_snapshot, fixture_json};
+    let video = fixture_json("instagram/video.json");
+    assert_snapshot("instagram/video.full.json", &Instagram::extract(video.clone(), false).unwrap());
+    for name in ["video", "sidecar", "image", "reel_music"] {
+        let data = fixture_json(&format!("instagram/{}.json", name));
+        assert_snapshot(&format!("instagram/{}.cut.json", name), &Instagram::extract(data, true).unwrap());
+    }
+}
+
+#[test]
+fn instagram_error_fixtures() {
+    use super::snapshot::fixture_json;
+    let err = Instagram::extract(fixture_json("instagram/not_found.json"), true).unwrap_err();
+    assert_eq!(err, ScrapeError::NotFound);
+    let err = Instagram::extract(fixture_json("instagram/rate_limited.json"), true).unwrap_err();
+    assert_eq!(err, ScrapeError::RateLimited);
+}
+
+#[test]
+fn instagram_targets() {
+    assert_eq!(Target::parse("https://www.instagram.com/reel/DHm7knuzl1D/"), Some(Target::Post("DHm7knuzl1D".into())));
+    assert_eq!(
+        Target::parse("https://www.instagram.com/stories/devfemibadmus/3471234567890123999/"),
+        Some(Target::Story("devfemibadmus".into()))
+    );
+    assert_eq!(
+        Target::parse("https://www.instagram.com/stories/highlights/17912345678901234/"),
+        Some(Target::Highlight("17912345678901234".into()))
+    );
+    assert_eq!(Target::parse("https://www.instagram.com/devfemibadmus/"), Some(Target::Profile("devfemibadmus".into())));
+    assert_eq!(Target::parse("https://www.instagram.com/explore/"), None);
+}
+
+#[test]
+fn instagram_profile_fixtures() {
+    use super::snapshot::{assert_snapshot, fixture_json};
+    let info = fixture_json("instagram/profile.json");
+    let user = &info["data"]["user"];
+    assert_snapshot("instagram/profile.cut.json", &Instagram::cut_profile(user, None));
+    let page = fixture_json("instagram/profile_page.json");
+    let next = Instagram::cut_profile(user, page.pointer("/data/user/edge_owner_to_timeline_media"));
+    assert_eq!(next.posts.items.len(), 2);
+    assert!(!next.posts.has_more);
+    assert_eq!(next.author.followers, Some(1520));
+}
+
+#[test]
+fn instagram_story_fixtures() {
+    use super::snapshot::{assert_snapshot, fixture_json};
+    for name in ["story", "highlight"] {
+        let data = fixture_json(&format!("instagram/{}.json", name));
+        assert_snapshot(&format!("instagram/{}.cut.json", name), &Instagram::extract_reel(data, true).unwrap());
+    }
+    let err = Instagram::extract_reel(fixture_json("instagram/story_expired.json"), true).unwrap_err();
+    assert_eq!(err, ScrapeError::NotFound);
+}
+
+#[test]
+fn instagram_comments_fixture() {
+    use super::snapshot::{assert_snapshot, fixture_json};
+    let video = fixture_json("instagram/video.json");
+    let comments = Instagram::cut_comments(&video["data"]["xdt_shortcode_media"]["edge_media_to_parent_comment"]);
+    assert_snapshot("instagram/comments.cut.json", &comments);
+}
+
+#[test]
+fn instagram_tokens_scrape() {
+    let base = Tokens::default();
+    assert_eq!(base.jazoest(), "2855");
+    let tokens = Tokens::scrape(&super::snapshot::fixture("instagram/home.html"), &base);
+    assert_eq!(tokens, Tokens {
+        doc_id: "9876543210123456".into(),
+        lsd: "AVfreshLsd1".into(),
+        rev: "1029384756".into(),
+        spin_t: "1760800000".into(),
+        csrf: "freshCsrfTok".into(),
+        // the page exports numeric doc ids for the paging operations, not their md5 hashes
+        timeline_hash: base.timeline_hash.clone(),
+        comments_hash: base.comments_hash.clone(),
+    });
+    assert_eq!(Tokens::scrape("<html></html>", &base), base);
+}
+
+#[test]
+fn instagram_csrf_header_matches_cookie() {
+    let tokens = Tokens { csrf: "cookieCsrfTok".into(), ..Tokens::default() };
+    let headers = Instagram::query_headers(&tokens);
+    let cookie = headers.get("cookie").unwrap().to_str().unwrap();
+    let header = headers.get("x-csrftoken").unwrap().to_str().unwrap();
+    assert_eq!(cookie.strip_prefix("csrftoken="), Some(header));
+    assert_eq!(header, "cookieCsrfTok");
+}
+
+#[tokio::test]
+async fn instagram_failed_bootstrap_is_not_cached() {
+    // no cassettes, so the home page can't be read
+    let http = Http::new(reqwest::Client::new(), super::http::Mode::Replay(std::env::temp_dir().join("mediascraper-no-cassettes")));
+    let store = Arc::new(Mutex::new(TokenStore { base: Tokens::default(), ttl: Duration::from_secs(3600), cached: None }));
+    let scraper = Instagram::new(http, Target::Post("DHm7knuzl1D".into()), true).with_tokens(store.clone());
+    assert_eq!(scraper.tokens(false).await, Tokens::default());
+    assert!(store.lock().unwrap().cached.is_none());
+}
+
+#[tokio::test]
+async fn instagram_stale_tokens_retry() {
+    let cassettes = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/cassettes");
+    let stale = Tokens {
+        doc_id: "1".into(),
+        lsd: "stale".into(),
+        rev: "1".into(),
+        spin_t: "1".into(),
+        csrf: "stale".into(),
+        timeline_hash: "stale".into(),
+        comments_hash: "stale".into(),
+    };
+    let store = Arc::new(Mutex::new(TokenStore {
+        base: Tokens::default(),
+        ttl: Duration::from_secs(3600),
+        cached: Some((Instant::now(), stale)),
+    }));
+
+    // the stale tokens get a 400, so fresh ones are scraped from the home page and the query retried
+    let http = Http::new(reqwest::Client::new(), super::http::Mode::Replay(cassettes));
+    let scraper = Instagram::new(http.clone(), Target::Post("DHm7knuzl1D".into()), true).with_tokens(store.clone());
+    let Scraped::Cut(post) = scraper.get_data().await.unwrap() else { panic!("expected cut data") };
+    assert_eq!(post.content.shortcode.as_deref(), Some("DHm7knuzl1D"));
+    let cached = store.lock().unwrap().cached.as_ref().unwrap().1.clone();
+    assert_eq!(cached.lsd, "AVfreshLsd1");
+    // the CSRF cookie is one of several `set-cookie` headers, not the last
+    assert_eq!(cached.csrf, "cookieCsrfTok");
+
+    // the configured paging hashes survive the refresh and are what the next page is asked with
+    assert_eq!(cached.timeline_hash, Tokens::default().timeline_hash);
+    let scraper = Instagram::new(http.clone(), Target::Profile("devfemibadmus".into()), true).with_tokens(store);
+    let profile = scraper.get_profile(Some("QVFCcursor1".into())).await.unwrap();
+    assert!(!profile.posts.items.is_empty());
+
+    // paging alone never bootstraps, since the hashes don't come from the home page
+    let empty = Arc::new(Mutex::new(TokenStore { base: Tokens::default(), ttl: Duration::from_secs(3600), cached: None }));
+    let scraper = Instagram::new(http, Target::Profile("devfemibadmus".into()), true).with_tokens(empty.clone());
+    scraper.get_profile(Some("QVFCcursor1".into())).await.unwrap();
+    assert!(empty.lock().unwrap().cached.is_none());
+}
@@ -0,0 +1,109 @@
+//! Finds values in large, loosely structured JSON (Facebook's Relay payloads) without
+//! guessing. Every lookup returns all the candidates with their JSON Pointer paths, so an
+//! extractor can score them against what it already knows (an id, sibling keys) and a wrong
+//! pick can be traced back with `Value::pointer(path)`.
+
+use serde_json::{Map, Value};
+
+/// One candidate: where it sits, as a JSON Pointer, and the value found there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<'a> {
+    pub path: String,
+    pub value: &'a Value,
+}
+
+impl<'a> Match<'a> {
+    /// Path of the object or array holding this value.
+    pub fn parent_path(&self) -> &str {
+        self.path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("")
+    }
+
+    /// The object holding this value, when it sits in one, read back from `root`.
+    pub fn parent<'r>(&self, root: &'r Value) -> Option<&'r Map<String, Value>> {
+        root.pointer(self.parent_path())?.as_object()
+    }
+}
+
+/// RFC 6901 escaping of one path segment.
+fn segment(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn walk<'a>(data: &'a Value, path: &mut String, found: &mut Vec<Match<'a>>, visit: &dyn Fn(&str, &'a Value) -> bool) {
+    let len = path.len();
+    match data {
+        Value::Object(map) => {
+            for (key, value) in map {
+                path.push('/');
+                path.push_str(&segment(key));
+                if visit(key, value) {
+                    found.push(Match { path: path.clone(), value });
+                }
+                walk(value, path, found, visit);
+                path.truncate(len);
+            }
+        }
+        Value::Array(arr) => {
+            for (i, value) in arr.iter().enumerate() {
+                path.push('/');
+                path.push_str(&i.to_string());
+                if visit("", value) {
+                    found.push(Match { path: path.clone(), value });
+                }
+                walk(value, path, found, visit);
+                path.truncate(len);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Every value stored under `key`, depth-first in document order (object keys sorted).
+pub fn find_all<'a>(data: &'a Value, key: &str) -> Vec<Match<'a>> {
+    let mut found = vec![];
+    walk(data, &mut String::new(), &mut found, &|k, _| k == key);
+    found
+}
+
+/// Every object `pred` accepts, the root included, depth-first in document order.
+pub fn find_objects<'a>(data: &'a Value, pred: &dyn Fn(&Map<String, Value>) -> bool) -> Vec<Match<'a>> {
+    let mut found = vec![];
+    if data.as_object().is_some_and(pred) {
+        found.push(Match { path: String::new(), value: data });
+    }
+    walk(data, &mut String::new(), &mut found, &|_, v| v.as_object().is_some_and(pred));
+    found
+}
+
+/// The highest scoring candidate, the earliest on ties. Candidates scored `None` are ruled out.
+pub fn best<'a>(matches: Vec<Match<'a>>, score: impl Fn(&Match<'a>) -> Option<i64>) -> Option<Match<'a>> {
+    let mut best: Option<(i64, Match<'a>)> = None;
+    for m in matches {
+        let Some(s) = score(&m) else { continue };
+        if best.as_ref().is_none_or(|(top, _)| s > *top) {
+            best = Some((s, m));
+        }
+    }
+    best.map(|(_, m)| m)
+}
+
+#[test]
+fn locate_finds_every_match_with_its_path() {
+    let data = serde_json::json!({
+        "a": { "data": { "id": "1" } },
+        "b": [{ "data": { "id": "2", "feedback": {} } }, { "x/y": { "data": null } }],
+    });
+    let found = find_all(&data, "data");
+    let paths: Vec<&str> = found.iter().map(|m| m.path.as_str()).collect();
+    assert_eq!(paths, ["/a/data", "/b/0/data", "/b/1/x~1y/data"]);
+    assert!(found.iter().all(|m| data.pointer(&m.path) == Some(m.value)));
+    assert_eq!(found[1].parent_path(), "/b/0");
+    assert!(found[1].parent(&data).unwrap().contains_key("data"));
+
+    let picked = best(found, |m| Some(m.value.get("feedback").is_some() as i64 * 10 + (m.value.get("id")? == "1") as i64));
+    assert_eq!(picked.unwrap().path, "/b/0/data");
+
+    let objects = find_objects(&data, &|o| o.contains_key("id"));
+    assert_eq!(objects.len(), 2);
+    assert!(best(objects, |_| None).is_none());
+}
@@ -1,4 +1,124 @@
 pub mod tiktok;
 pub mod facebook;
 pub mod instagram;
+pub mod model;
+pub mod error;
+pub mod http;
+pub mod locate;
 // pub mod test;
+#[cfg(test)]
+mod snapshot;
+
+use futures::future::BoxFuture;
+use reqwest::header::HeaderMap;
+
+pub use error::ScrapeError;
+use http::Http;
+use model::{Comment, Page, Profile, Scraped, Sound};
+
+pub type Outcome = Result<Scraped, ScrapeError>;
+
+/// A media site we can scrape. One value is built per request from the URL being resolved.
+pub trait Platform: Send {
+    fn matches(url: &str) -> bool where Self: Sized;
+
+    /// What cut output calls the platform, also accepted as a `platform=` hint by `/proxy`.
+    fn name() -> &'static str where Self: Sized;
+
+    fn normalize(url: &str) -> String where Self: Sized {
+        url.to_string()
+    }
+
+    fn from_url(url: &str, cut: bool, http: Http) -> Self where Self: Sized;
+
+    /// CDN hosts (and their subdomains) the platform serves media from.
+    fn media_hosts() -> &'static [&'static str] where Self: Sized;
+
+    /// Headers the CDN expects when we fetch media on a user's behalf.
+    fn media_headers() -> HeaderMap where Self: Sized;
+
+    fn fetch(&mut self) -> BoxFuture<'_, Outcome>;
+
+    /// Author card and a page of their posts, for profile URLs; `cursor` comes from the
+    /// previous page.
+    fn profile(&mut self, _cursor: Option<String>) -> BoxFuture<'_, Result<Profile, ScrapeError>> {
+        Box::pin(async { Err(ScrapeError::Unsupported) })
+    }
+
+    /// A page of comments on the post, replies nested under their parent.
+    fn comments(&mut self, _cursor: Option<String>) -> BoxFuture<'_, Result<Page<Comment>, ScrapeError>> {
+        Box::pin(async { Err(ScrapeError::Unsupported) })
+    }
+
+    /// A page of the replies to comment `comment_id` on the post, for threads longer than the
+    /// replies sent along with the comment.
+    fn replies(&mut self, _comment_id: String, _cursor: Option<String>) -> BoxFuture<'_, Result<Page<Comment>, ScrapeError>> {
+        Box::pin(async { Err(ScrapeError::Unsupported) })
+    }
+
+    /// The track and a page of posts using it, for sound URLs.
+    fn sound(&mut self, _cursor: Option<String>) -> BoxFuture<'_, Result<Sound, ScrapeError>> {
+        Box::pin(async { Err(ScrapeError::Unsupported) })
+    }
+}
+
+struct Entry {
+    name: &'static str,
+    matches: fn(&str) -> bool,
+    build: fn(&str, bool, Http) -> Box<dyn Platform>,
+    media_hosts: &'static [&'static str],
+    media_headers: fn() -> HeaderMap,
+}
+
+fn build<T: Platform + 'static>(url: &str, cut: bool, http: Http) -> Box<dyn Platform> {
+    Box::new(T::from_url(&T::normalize(url), cut, http))
+}
+
+/// Ordered list of platforms; the first one whose `matches` accepts a URL handles it.
+pub struct Registry {
+    entries: Vec<Entry>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    pub fn register<T: Platform + 'static>(mut self) -> Self {
+        self.entries.push(Entry {
+            name: T::name(),
+            matches: T::matches,
+            build: build::<T>,
+            media_hosts: T::media_hosts(),
+            media_headers: T::media_headers,
+        });
+        self
+    }
+
+    pub fn resolve(&self, url: &str, cut: bool, http: Http) -> Option<Box<dyn Platform>> {
+        self.entries.iter()
+            .find(|e| (e.matches)(url))
+            .map(|e| (e.build)(url, cut, http))
+    }
+
+    /// Headers for fetching media from `host`, or `None` if no platform serves media from it.
+    /// Where platforms share a CDN, `platform` picks whose headers are sent; otherwise the
+    /// first registered one wins.
+    pub fn media_headers(&self, host: &str, platform: Option<&str>) -> Option<HeaderMap> {
+        let mut serving = self.entries.iter()
+            .filter(|e| e.media_hosts.iter().any(|h| host == *h || host.ends_with(&format!(".{}", h))));
+        let first = serving.clone().next();
+        serving.find(|e| Some(e.name) == platform)
+            .or(first)
+            .map(|e| (e.media_headers)())
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+            .register::<tiktok::TikTokv2>()
+            .register::<facebook::Facebook>()
+            .register::<instagram::Instagram>()
+    }
+}
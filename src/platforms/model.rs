@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// What a scraper hands back: the raw upstream JSON, or the shared cut schema.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Scraped {
+    Full(Value),
+    Cut(Box<Post>),
+}
+
+/// Cut output, identical in shape for every platform.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Post {
+    pub platform: String,
+    pub content: Content,
+    pub author: Author,
+    pub media: Vec<MediaItem>,
+    pub music: Option<Music>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Content {
+    pub id: Option<String>,
+    pub shortcode: Option<String>,
+    pub title: Option<String>,
+    pub desc: Option<String>,
+    pub cover: Option<String>,
+    pub views: Option<u64>,
+    pub plays: Option<u64>,
+    pub likes: Option<u64>,
+    pub comments: Option<u64>,
+    pub saves: Option<u64>,
+    pub shares: Option<u64>,
+    /// Per-reaction counts, for platforms with more reactions than likes.
+    pub reactions: Option<Reactions>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Reactions {
+    pub like: Option<u64>,
+    pub love: Option<u64>,
+    pub haha: Option<u64>,
+    pub wow: Option<u64>,
+    pub sad: Option<u64>,
+    pub angry: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Author {
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub username: Option<String>,
+    pub verified: Option<bool>,
+    pub image: Option<String>,
+    pub bio: Option<String>,
+    pub location: Option<String>,
+    pub followers: Option<u64>,
+    pub following: Option<u64>,
+    pub posts: Option<u64>,
+    pub private: Option<bool>,
+    /// Link from the profile, when the author set one.
+    pub website: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    #[default]
+    Video,
+    Image,
+    Audio,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MediaItem {
+    pub id: Option<String>,
+    pub kind: MediaKind,
+    pub address: Option<String>,
+    pub cover: Option<String>,
+    /// Accessibility text describing the image.
+    pub alt: Option<String>,
+    /// Separate audio stream for the variants served without sound, i.e. the ones with a
+    /// `video/` mime type.
+    pub audio: Option<String>,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub size: Option<u64>,
+    pub views: Option<u64>,
+    pub plays: Option<u64>,
+    /// Unix time the item was posted, for stories.
+    pub timestamp: Option<u64>,
+    /// Unix time after which the platform stops serving it, for stories.
+    pub expires_at: Option<u64>,
+    /// `false` for videos the platform says are silent.
+    pub has_audio: Option<bool>,
+    /// Set for live broadcasts and their replays.
+    pub live: Option<Live>,
+    pub variants: Vec<Variant>,
+    /// Cover image sizes for videos, best-first.
+    pub covers: Vec<Variant>,
+}
+
+/// Streaming details of a live broadcast or its replay.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Live {
+    /// `true` while the broadcast is still on air.
+    pub ongoing: bool,
+    /// Adaptive streaming manifest URL, when the platform gives one.
+    pub manifest: Option<String>,
+    /// `dash` or `hls`, the format of `manifest`.
+    pub protocol: Option<String>,
+    /// Renditions only reachable through `manifest`, best-first. Each one's `address` is the
+    /// manifest itself, so unlike `variants` they can't be downloaded as files.
+    #[serde(default)]
+    pub renditions: Vec<Variant>,
+}
+
+/// One encoding of a media item; `variants` are ordered best-first.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Variant {
+    pub name: Option<String>,
+    pub address: String,
+    /// Every URL the platform offers for this encoding, `address` included.
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    /// Whether the platform burned its watermark into the file, when it says.
+    #[serde(default)]
+    pub watermark: Option<bool>,
+    pub size: Option<u64>,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub bitrate: Option<u64>,
+    pub codec: Option<String>,
+    pub mime_type: Option<String>,
+}
+
+/// One page of a longer list; pass `cursor` back to get the next page.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub cursor: Option<String>,
+    pub has_more: bool,
+}
+
+/// A post as listed on a profile: enough to draw a grid and scrape it later from `url`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PostSummary {
+    pub id: Option<String>,
+    pub shortcode: Option<String>,
+    pub url: Option<String>,
+    pub kind: MediaKind,
+    pub cover: Option<String>,
+    pub desc: Option<String>,
+    pub views: Option<u64>,
+    pub likes: Option<u64>,
+    pub comments: Option<u64>,
+    /// Unix time the post was published.
+    pub timestamp: Option<u64>,
+}
+
+/// Profile output: the author card and a page of their recent posts.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub platform: String,
+    pub author: Author,
+    pub posts: Page<PostSummary>,
+}
+
+/// Sound output: the track and a page of posts that use it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Sound {
+    pub platform: String,
+    pub music: Music,
+    /// How many posts use the sound, when the platform says.
+    pub uses: Option<u64>,
+    pub posts: Page<PostSummary>,
+}
+
+/// A comment and the replies that came with it; `reply_count` can exceed `replies.len()`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Comment {
+    pub id: Option<String>,
+    pub text: Option<String>,
+    pub author: Author,
+    pub likes: Option<u64>,
+    /// Unix time the comment was posted.
+    pub timestamp: Option<u64>,
+    pub reply_count: Option<u64>,
+    pub replies: Vec<Comment>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Music {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub cover: Option<String>,
+    pub duration: Option<u64>,
+    /// The creator's own sound rather than a licensed track.
+    pub original: Option<bool>,
+    pub src: Option<String>,
+}
+
+/// Reads a string, also accepting numbers since ids come back both ways.
+pub fn string(v: Option<&Value>) -> Option<String> {
+    match v? {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Reads a count, also accepting numeric strings and floats.
+pub fn number(v: Option<&Value>) -> Option<u64> {
+    match v? {
+        Value::Number(n) => n.as_u64().or_else(|| n.as_f64().map(|f| f as u64)),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+pub fn boolean(v: Option<&Value>) -> Option<bool> {
+    v?.as_bool()
+}
@@ -0,0 +1,36 @@
+//! Offline test helpers. Recorded pages live under `fixtures/<platform>/`, and the
+//! expected output of each scraper sits next to them as `<name>.cut.json` / `<name>.full.json`.
+//! Run the tests with `UPDATE_SNAPSHOTS=1` to rewrite the expected files after a deliberate change.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+
+fn path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)
+}
+
+pub fn fixture(name: &str) -> String {
+    std::fs::read_to_string(path(name)).unwrap_or_else(|e| panic!("missing fixture {}: {}", name, e))
+}
+
+pub fn fixture_json(name: &str) -> Value {
+    serde_json::from_str(&fixture(name)).unwrap_or_else(|e| panic!("bad fixture {}: {}", name, e))
+}
+
+pub fn assert_snapshot<T: Serialize>(name: &str, actual: &T) {
+    let actual = serde_json::to_value(actual).unwrap();
+    let file = path(name);
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        let pretty = serde_json::to_string_pretty(&actual).unwrap();
+        std::fs::write(&file, pretty + "\n").unwrap();
+        return;
+    }
+    let expected = fixture_json(name);
+    assert!(
+        actual == expected,
+        "{} does not match, rerun with UPDATE_SNAPSHOTS=1 if this is intended\n{}",
+        name,
+        serde_json::to_string_pretty(&actual).unwrap()
+    );
+}
@@ -1,20 +1,51 @@
-use reqwest::Client;
 use scraper::{Html, Selector};
-use serde_json::{json, Value};
-use futures::future::join_all;
+use serde_json::Value;
+use futures::future::BoxFuture;
+use regex::Regex;
+
+use super::{Outcome, Platform, ScrapeError};
+use super::http::{encode, Http, Response};
+use super::model::{boolean, number, string, Author, Comment, Content, MediaItem, MediaKind, Music, Page, Post, PostSummary, Profile, Scraped, Sound, Variant};
+
+/// What a TikTok URL points at.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    /// A video or photo post, or a short link to one.
+    Video,
+    /// `/@<username>`.
+    Profile(String),
+    /// `/music/<name>-<id>`, holding the id.
+    Music(String),
+}
+
+impl Target {
+    pub fn parse(url: &str) -> Option<Self> {
+        let profile = Regex::new(r"tiktok\.com/@([A-Za-z0-9._-]+)/?(?:[?#].*)?$").unwrap();
+        if let Some(cap) = profile.captures(url) {
+            return Some(Self::Profile(cap[1].to_string()));
+        }
+        let music = Regex::new(r"tiktok\.com/music/(?:[^/?#]*-)?(\d+)").unwrap();
+        if let Some(cap) = music.captures(url) {
+            return Some(Self::Music(cap[1].to_string()));
+        }
+        Regex::new(r"tiktok\.com/.*/").unwrap().is_match(url).then_some(Self::Video)
+    }
+}
 
 pub struct TikTokv2 {
     url: String,
+    target: Target,
     cut: bool,
-    client: Client,
+    http: Http,
 }
 
 impl TikTokv2 {
-    pub fn new(url: &str, cut: bool, client: Client) -> Self {
+    pub fn new(url: &str, cut: bool, http: Http) -> Self {
         Self {
-            url: url.replace("/photo", "/video"),
+            url: url.to_string(),
+            target: Target::parse(url).unwrap_or(Target::Video),
             cut,
-            client,
+            http,
         }
     }
 
@@ -28,175 +59,574 @@ impl TikTokv2 {
         headers
     }
 
-    async fn get(&self, url: &str) -> reqwest::Result<reqwest::Response> {
-        self.client.get(url).headers(Self::headers()).send().await
+    async fn get(&self, url: &str) -> Result<Response, ScrapeError> {
+        self.http.get(url, Self::headers()).await
     }
 
-    async fn fetch_json(&mut self) -> Result<Value, String> {
+    async fn fetch_json(&mut self) -> Result<Value, ScrapeError> {
         if self.url.contains("vm.tiktok.com") {
-            let resp = self.get(&self.url).await.map_err(|_| "Failed to follow vm.tiktok.com redirect")?;
-            self.url = resp.url().to_string();
+            let resp = self.get(&self.url).await?;
+            self.url = resp.url;
         }
 
-        let resp = self.get(&self.url).await.map_err(|e| format!("Request error: {}", e))?;
-        if resp.status() != 200 {
-            return Err(format!("Failed to fetch page content: {}", resp.status()));
+        let resp = self.get(&self.url).await?;
+        if let Some(e) = resp.error() {
+            return Err(e);
         }
+        Self::parse_html(&resp.text())
+    }
+
+    /// Pulls `webapp.video-detail` out of a video page.
+    pub fn parse_html(text: &str) -> Result<Value, ScrapeError> {
+        Self::rehydration(text, "webapp.video-detail")
+    }
 
-        let text = resp.text().await.map_err(|e| format!("Read body failed: {}", e))?;
-        let document = Html::parse_document(&text);
+    /// One `__DEFAULT_SCOPE__` entry of a page's `__UNIVERSAL_DATA_FOR_REHYDRATION__`.
+    fn rehydration(text: &str, key: &str) -> Result<Value, ScrapeError> {
+        let document = Html::parse_document(text);
         let script_sel = Selector::parse("script#__UNIVERSAL_DATA_FOR_REHYDRATION__").unwrap();
-        let script = document.select(&script_sel).next().ok_or("No script tag found")?;
+        let script = document.select(&script_sel).next()
+            .ok_or_else(|| ScrapeError::UpstreamChanged("No script tag found".into()))?;
         let script_text = script.text().collect::<Vec<_>>().join("").trim().to_string();
-        let json_data: Value = serde_json::from_str(&script_text).map_err(|_| "Invalid JSON in script")?;
+        let json_data: Value = serde_json::from_str(&script_text)
+            .map_err(|_| ScrapeError::UpstreamChanged("Invalid JSON in script".into()))?;
         let json_data = json_data.get("__DEFAULT_SCOPE__")
-            .and_then(|x| x.get("webapp.video-detail"))
+            .and_then(|x| x.get(key))
             .cloned()
-            .ok_or("No webapp.video-detail in JSON")?;
+            .ok_or_else(|| ScrapeError::UpstreamChanged(format!("No {} in JSON", key)))?;
+        Self::check_status(&json_data)?;
         Ok(json_data)
     }
 
-    fn err(&self, message: &str, error_message: &str) -> Value {
-        json!({
-            "error": true,
-            "message": message,
-            "error_message": error_message,
-        })
+    /// TikTok answers 200 for missing or hidden posts and puts the reason in `statusCode`
+    /// (`status_code` on the comment API).
+    fn check_status(detail: &Value) -> Result<(), ScrapeError> {
+        let code = detail.get("statusCode").or_else(|| detail.get("status_code"));
+        match code.and_then(|c| c.as_i64()).unwrap_or(0) {
+            0 => Ok(()),
+            10202 | 10204 | 10221 => Err(ScrapeError::NotFound),
+            10216 | 10222 => Err(ScrapeError::Private),
+            code => Err(ScrapeError::UpstreamChanged(format!(
+                "statusCode {}: {}", code,
+                detail.get("statusMsg").or_else(|| detail.get("status_msg")).and_then(|m| m.as_str()).unwrap_or("")
+            ))),
+        }
+    }
+
+    /// Author from a `user`/`author` object and its `stats`/`authorStats`.
+    fn author(user: Option<&Value>, stats: Option<&Value>) -> Author {
+        let field = |key: &str| user.and_then(|u| u.get(key));
+        Author {
+            id: string(field("id")),
+            name: string(field("nickname")),
+            username: string(field("uniqueId")),
+            verified: boolean(field("verified")),
+            image: string(field("avatarMedium")),
+            bio: string(field("signature")),
+            followers: number(stats.and_then(|s| s.get("followerCount"))),
+            following: number(stats.and_then(|s| s.get("followingCount"))),
+            posts: number(stats.and_then(|s| s.get("videoCount"))),
+            private: boolean(field("privateAccount")),
+            website: string(field("bioLink").and_then(|b| b.get("link"))),
+            ..Default::default()
+        }
+    }
+
+    fn music(item: &Value) -> Option<Music> {
+        item.get("music").map(Self::track)
+    }
+
+    fn track(music: &Value) -> Music {
+        Music {
+            id: string(music.get("id")),
+            title: string(music.get("title")),
+            author: string(music.get("authorName")),
+            cover: string(music.get("coverMedium")),
+            duration: number(music.get("duration")),
+            original: boolean(music.get("original")),
+            src: string(music.get("playUrl")),
+        }
     }
 
-    pub async fn get_data(&mut self) -> (Value, u16) {
-        let data = match self.fetch_json().await {
-            Ok(d) => d,
-            Err(e) => return (self.err(&e, &e), 502),
+    /// One variant per file format in a slide's `imageURL.urlList`, JPEG first since every
+    /// client can open it, with the CDN mirrors of that format.
+    fn image_variants(img: &Value) -> Vec<Variant> {
+        let urls: Vec<String> = img.pointer("/imageURL/urlList")
+            .and_then(|l| l.as_array())
+            .map(|l| l.iter().filter_map(|u| string(Some(u))).collect())
+            .unwrap_or_default();
+        let format = |url: &str| -> String {
+            let path = url.split('?').next().unwrap_or(url);
+            match path.rsplit_once('.').map(|(_, ext)| ext) {
+                Some("jpg") | Some("jpeg") => "jpeg".to_string(),
+                Some(ext) if !ext.contains('/') => ext.to_string(),
+                _ => "image".to_string(),
+            }
         };
+        let mut formats: Vec<String> = vec![];
+        for url in &urls {
+            let f = format(url);
+            if !formats.contains(&f) {
+                formats.push(f);
+            }
+        }
+        formats.sort_by_key(|f| f != "jpeg");
+        formats.into_iter().map(|f| {
+            let mirrors: Vec<String> = urls.iter().filter(|u| format(u) == f).cloned().collect();
+            Variant {
+                address: mirrors[0].clone(),
+                name: Some(f),
+                mirrors,
+                watermark: Some(false),
+                width: number(img.get("imageWidth")),
+                height: number(img.get("imageHeight")),
+                ..Default::default()
+            }
+        }).collect()
+    }
+
+    fn last_url(list: Option<&Value>) -> Option<String> {
+        string(list.and_then(|l| l.as_array()).and_then(|a| a.last()))
+    }
+
+    /// `h264`/`h265` from TikTok's codec names such as `h265_hvc1` or `bytevc1`.
+    fn codec(name: Option<&Value>) -> Option<String> {
+        let name = string(name)?.to_lowercase();
+        Some(match name.as_str() {
+            n if n.contains("265") || n.contains("hevc") || n.contains("bytevc1") => "h265".to_string(),
+            n if n.contains("264") || n.contains("avc") => "h264".to_string(),
+            _ => name,
+        })
+    }
+
+    /// Every `bitrateInfo` gear, best-first, followed by `playAddr` and the watermarked
+    /// `downloadAddr`.
+    fn variants(video: &Value) -> Vec<Variant> {
+        let gears = video.get("bitrateInfo").and_then(|b| b.as_array()).cloned().unwrap_or_default();
+        let mut variants: Vec<Variant> = gears.iter().filter_map(|q| {
+            let play = q.get("PlayAddr");
+            let urls = play.and_then(|p| p.get("UrlList")).and_then(|l| l.as_array()).cloned().unwrap_or_default();
+            let mirrors: Vec<String> = urls.iter()
+                .filter_map(|u| string(Some(u)))
+                .map(|u| u.replace("https://www.tiktok.com", "https://api16-normal-useast5.tiktokv.us"))
+                .collect();
+            Some(Variant {
+                name: string(q.get("GearName")),
+                address: mirrors.last()?.clone(),
+                size: number(play.and_then(|p| p.get("DataSize"))),
+                width: number(play.and_then(|p| p.get("Width"))),
+                height: number(play.and_then(|p| p.get("Height"))),
+                bitrate: number(q.get("Bitrate")),
+                codec: Self::codec(q.get("CodecType")),
+                watermark: Some(false),
+                mirrors,
+                ..Default::default()
+            })
+        }).collect();
+        variants.sort_by_key(|v| std::cmp::Reverse((v.width.zip(v.height).map(|(w, h)| w * h), v.bitrate)));
 
-        if !self.cut {
-            return (data.clone(), 200);
+        for (name, key, watermark) in [("play", "playAddr", false), ("download", "downloadAddr", true)] {
+            if let Some(address) = string(video.get(key)) {
+                variants.push(Variant {
+                    name: Some(name.to_string()),
+                    mirrors: vec![address.clone()],
+                    address,
+                    width: number(video.get("width")),
+                    height: number(video.get("height")),
+                    bitrate: number(video.get("bitrate")),
+                    codec: Self::codec(video.get("codecType")),
+                    watermark: Some(watermark),
+                    ..Default::default()
+                });
+            }
         }
+        variants
+    }
 
-        let item = match data.get("itemInfo").and_then(|x| x.get("itemStruct")) {
-            Some(v) => v,
-            None => return (self.err("something went wrong", "unable to get item from itemStruct"), 502),
+    pub fn cut(item: &Value) -> Post {
+        let stats = item.get("stats");
+        let mut content = Content {
+            id: string(item.get("id")),
+            desc: string(item.get("desc")),
+            views: number(stats.and_then(|s| s.get("playCount"))),
+            likes: number(stats.and_then(|s| s.get("diggCount"))),
+            comments: number(stats.and_then(|s| s.get("commentCount"))),
+            saves: number(stats.and_then(|s| s.get("collectCount"))),
+            shares: number(stats.and_then(|s| s.get("shareCount"))),
+            cover: string(item.get("video").and_then(|v| v.get("cover"))),
+            ..Default::default()
         };
 
-        // Handle videos in parallel
-        if let Some(bitrate_info) = item.get("video").and_then(|v| v.get("bitrateInfo")).and_then(|b| b.as_array()) {
-            let videos_fut = bitrate_info.iter().enumerate().map(|(i, q)| async move {
-                let size = q.get("PlayAddr").and_then(|p| p.get("DataSize")).cloned().unwrap_or(json!("N/A"));
-                let addr = q.get("PlayAddr")
-                    .and_then(|p| p.get("UrlList"))
-                    .and_then(|u| u.as_array())
-                    .and_then(|a| a.last())
-                    .cloned()
-                    .unwrap_or(json!("N/A"))
-                    .as_str()
-                    .unwrap_or("N/A")
-                    .replace("https://www.tiktok.com", "https://api16-normal-useast5.tiktokv.us");
-                json!({ format!("quality_{}", i): {"size": size, "address": addr} })
+        let mut media = vec![];
+        if let Some(video) = item.get("video").filter(|v| v.get("bitrateInfo").is_some()) {
+            let variants = Self::variants(video);
+            let preferred = variants.iter()
+                .find(|v| v.watermark == Some(false) && v.codec.as_deref() == Some("h264"))
+                .or(variants.first());
+            media.push(MediaItem {
+                id: content.id.clone(),
+                kind: MediaKind::Video,
+                address: preferred.map(|v| v.address.clone()),
+                cover: content.cover.clone(),
+                width: preferred.and_then(|v| v.width),
+                height: preferred.and_then(|v| v.height),
+                size: preferred.and_then(|v| v.size),
+                variants,
+                ..Default::default()
             });
-            let videos: Vec<Value> = join_all(videos_fut).await;
-
-            return (json!({
-                "platform": "tiktok",
-                "is_video": true,
-                "content": {
-                    "id": item.get("id").cloned().unwrap_or(json!("N/A")),
-                    "desc": item.get("desc").cloned().unwrap_or(json!("N/A")),
-                    "views": item.get("stats").and_then(|s| s.get("playCount")).cloned().unwrap_or(json!(0)),
-                    "likes": item.get("stats").and_then(|s| s.get("diggCount")).cloned().unwrap_or(json!(0)),
-                    "comments": item.get("stats").and_then(|s| s.get("commentCount")).cloned().unwrap_or(json!(0)),
-                    "saves": item.get("stats").and_then(|s| s.get("collectCount")).cloned().unwrap_or(json!(0)),
-                    "share": item.get("stats").and_then(|s| s.get("shareCount")).cloned().unwrap_or(json!(0)),
-                    "cover": item.get("video").and_then(|v| v.get("cover")).cloned().unwrap_or(json!("N/A")),
-                },
-                "author": {
-                    "name": item.get("author").and_then(|a| a.get("nickname")).cloned().unwrap_or(json!("N/A")),
-                    "username": item.get("author").and_then(|a| a.get("uniqueId")).cloned().unwrap_or(json!("N/A")),
-                    "verified": item.get("author").and_then(|a| a.get("verified")).cloned().unwrap_or(json!(false)),
-                    "image": item.get("author").and_then(|a| a.get("avatarMedium")).cloned().unwrap_or(json!("N/A")),
-                    "bio": item.get("author").and_then(|a| a.get("signature")).cloned().unwrap_or(json!("N/A")),
-                },
-                "videos": videos,
-                "music": {
-                    "author": item.get("music").and_then(|m| m.get("authorName")).cloned().unwrap_or(json!("N/A")),
-                    "title": item.get("music").and_then(|m| m.get("title")).cloned().unwrap_or(json!("N/A")),
-                    "cover": item.get("music").and_then(|m| m.get("coverMedium")).cloned().unwrap_or(json!("N/A")),
-                    "duration": item.get("music").and_then(|m| m.get("duration")).cloned().unwrap_or(json!("N/A")),
-                    "src": item.get("music").and_then(|m| m.get("playUrl")).cloned().unwrap_or(json!("N/A")),
+        } else if let Some(post) = item.get("imagePost") {
+            content.title = string(post.get("title"));
+            content.cover = Self::last_url(post.get("cover").and_then(|c| c.get("imageURL")).and_then(|u| u.get("urlList")));
+            let images = post.get("images").and_then(|x| x.as_array()).cloned().unwrap_or_default();
+            media = images.iter().enumerate().map(|(i, img)| {
+                let variants = Self::image_variants(img);
+                MediaItem {
+                    id: Some(format!("image_{}", i)),
+                    kind: MediaKind::Image,
+                    address: variants.first().map(|v| v.address.clone()),
+                    width: number(img.get("imageWidth")),
+                    height: number(img.get("imageHeight")),
+                    variants,
+                    ..Default::default()
                 }
-            }), 200);
+            }).collect();
         }
 
-        // Handle images
-        let images = item.get("imagePost")
-            .and_then(|p| p.get("images"))
-            .and_then(|x| x.as_array())
-            .cloned()
-            .unwrap_or_default();
+        Post {
+            platform: "tiktok".to_string(),
+            content,
+            author: Author {
+                location: string(item.get("locationCreated")),
+                ..Self::author(item.get("author"), item.get("authorStats"))
+            },
+            media,
+            music: Self::music(item),
+        }
+    }
 
-        let out_images: Vec<Value> = images.iter().enumerate().map(|(i, img)| {
-            let addr = img.get("imageURL")
-                .and_then(|u| u.get("urlList"))
-                .and_then(|l| l.as_array())
-                .and_then(|a| a.last())
-                .cloned()
-                .unwrap_or(json!(""));
-            let size = img.get("imageHeight").cloned().unwrap_or(json!("N/A"));
-            json!({ format!("image_{}", i): {"address": addr, "size": size} })
-        }).collect();
+    pub async fn get_data(&mut self) -> Outcome {
+        match self.target {
+            Target::Profile(_) => return Err(ScrapeError::BadRequest("profiles are served by /api/profile".into())),
+            Target::Music(_) => return Err(ScrapeError::BadRequest("sounds are served by /api/music".into())),
+            Target::Video => {}
+        }
+        let data = self.fetch_json().await?;
+        Self::extract(data, self.cut)
+    }
+
+    /// The profile card from `webapp.user-detail` and a page of posts from `item_list`.
+    pub async fn get_profile(&mut self, cursor: Option<String>) -> Result<Profile, ScrapeError> {
+        let Target::Profile(username) = &self.target else {
+            return Err(ScrapeError::BadRequest("not a profile URL".into()));
+        };
+        let resp = self.get(&format!("https://www.tiktok.com/@{}", username)).await?;
+        if let Some(e) = resp.error() {
+            return Err(e);
+        }
+        let detail = Self::rehydration(&resp.text(), "webapp.user-detail")?;
+        let sec_uid = string(detail.pointer("/userInfo/user/secUid"))
+            .ok_or_else(|| ScrapeError::UpstreamChanged("profile has no secUid".into()))?;
+
+        let url = format!(
+            "https://www.tiktok.com/api/post/item_list/?aid=1988&count=30&cursor={}&secUid={}",
+            encode(cursor.as_deref().unwrap_or("0")),
+            encode(&sec_uid)
+        );
+        let resp = self.get(&url).await?;
+        if let Some(e) = resp.error() {
+            return Err(e);
+        }
+        let list: Value = resp.json()?;
+        Self::check_status(&list)?;
+        Ok(Self::cut_profile(&detail, &list))
+    }
+
+    /// The track from `webapp.music-detail` and a page of posts from `music/item_list`.
+    pub async fn get_sound(&mut self, cursor: Option<String>) -> Result<Sound, ScrapeError> {
+        let Target::Music(id) = &self.target else {
+            return Err(ScrapeError::BadRequest("not a sound URL".into()));
+        };
+        let url = format!(
+            "https://www.tiktok.com/api/music/item_list/?aid=1988&count=30&cursor={}&musicID={}",
+            encode(cursor.as_deref().unwrap_or("0")),
+            id
+        );
+        let resp = self.get(&self.url).await?;
+        if let Some(e) = resp.error() {
+            return Err(e);
+        }
+        let detail = Self::rehydration(&resp.text(), "webapp.music-detail")?;
 
-        (json!({
-            "platform": "tiktok",
-            "is_image": true,
-            "content": {
-                "id": item.get("id").cloned().unwrap_or(json!("N/A")),
-                "desc": item.get("desc").cloned().unwrap_or(json!("N/A")),
-                "title": item.get("imagePost").and_then(|p| p.get("title")).cloned().unwrap_or(json!("N/A")),
-                "views": item.get("stats").and_then(|s| s.get("playCount")).cloned().unwrap_or(json!(0)),
-                "likes": item.get("stats").and_then(|s| s.get("diggCount")).cloned().unwrap_or(json!(0)),
-                "comments": item.get("stats").and_then(|s| s.get("commentCount")).cloned().unwrap_or(json!(0)),
-                "saves": item.get("stats").and_then(|s| s.get("collectCount")).cloned().unwrap_or(json!(0)),
-                "share": item.get("stats").and_then(|s| s.get("shareCount")).cloned().unwrap_or(json!(0)),
-                "cover": item.get("imagePost")
-                    .and_then(|p| p.get("cover"))
-                    .and_then(|c| c.get("imageURL"))
-                    .and_then(|u| u.get("urlList"))
-                    .and_then(|l| l.as_array())
-                    .and_then(|a| a.last())
-                    .cloned()
-                    .unwrap_or(json!("N/A")),
+        let resp = self.get(&url).await?;
+        if let Some(e) = resp.error() {
+            return Err(e);
+        }
+        let list: Value = resp.json()?;
+        Self::check_status(&list)?;
+        Ok(Self::cut_sound(&detail, &list))
+    }
+
+    /// Builds the sound from `webapp.music-detail` and a `music/item_list` page.
+    pub fn cut_sound(detail: &Value, list: &Value) -> Sound {
+        let items = list.get("itemList").and_then(|i| i.as_array()).cloned().unwrap_or_default();
+        let music = detail.pointer("/musicInfo/music");
+        Sound {
+            platform: "tiktok".to_string(),
+            music: Music {
+                cover: music.and_then(|m| string(m.get("coverLarge"))),
+                ..music.map(Self::track).unwrap_or_default()
             },
-            "author": {
-                "name": item.get("author").and_then(|a| a.get("nickname")).cloned().unwrap_or(json!("N/A")),
-                "username": item.get("author").and_then(|a| a.get("uniqueId")).cloned().unwrap_or(json!("N/A")),
-                "verified": item.get("author").and_then(|a| a.get("verified")).cloned().unwrap_or(json!(false)),
-                "image": item.get("author").and_then(|a| a.get("avatarMedium")).cloned().unwrap_or(json!("N/A")),
-                "location": item.get("locationCreated").cloned().unwrap_or(json!("N/A")),
+            uses: number(detail.pointer("/musicInfo/stats/videoCount")),
+            posts: Page {
+                items: items.iter().map(Self::summary).collect(),
+                cursor: string(list.get("cursor")),
+                has_more: boolean(list.get("hasMore")).unwrap_or(false),
             },
-            "images": out_images,
-            "music": {
-                "author": item.get("music").and_then(|m| m.get("authorName")).cloned().unwrap_or(json!("N/A")),
-                "title": item.get("music").and_then(|m| m.get("title")).cloned().unwrap_or(json!("N/A")),
-                "cover": item.get("music").and_then(|m| m.get("coverMedium")).cloned().unwrap_or(json!("N/A")),
-                "duration": item.get("music").and_then(|m| m.get("duration")).cloned().unwrap_or(json!("N/A")),
-                "src": item.get("playUrl").cloned().unwrap_or(json!("N/A")),
-            }
-        }), 200)
+        }
+    }
+
+    /// The post id, following a short link first if needed.
+    async fn video_id(&mut self) -> Result<String, ScrapeError> {
+        if self.url.contains("vm.tiktok.com") {
+            let resp = self.get(&self.url).await?;
+            self.url = resp.url;
+        }
+        Regex::new(r"/(?:video|photo)/(\d+)").unwrap()
+            .captures(&self.url)
+            .map(|cap| cap[1].to_string())
+            .ok_or_else(|| ScrapeError::BadRequest("comments need a post URL".into()))
+    }
+
+    /// A page of top-level comments from `comment/list`, each with the replies sent inline.
+    pub async fn get_comments(&mut self, cursor: Option<String>) -> Result<Page<Comment>, ScrapeError> {
+        let id = self.video_id().await?;
+        let url = format!(
+            "https://www.tiktok.com/api/comment/list/?aid=1988&aweme_id={}&count=20&cursor={}",
+            id,
+            encode(cursor.as_deref().unwrap_or("0"))
+        );
+        let resp = self.get(&url).await?;
+        if let Some(e) = resp.error() {
+            return Err(e);
+        }
+        let list: Value = resp.json()?;
+        Self::check_status(&list)?;
+        Ok(Self::cut_comments(&list))
+    }
+
+    /// A page of the replies to `comment_id` from `comment/list/reply`, which TikTok pages the
+    /// same way as top-level comments.
+    pub async fn get_replies(&mut self, comment_id: &str, cursor: Option<String>) -> Result<Page<Comment>, ScrapeError> {
+        let id = self.video_id().await?;
+        let url = format!(
+            "https://www.tiktok.com/api/comment/list/reply/?aid=1988&comment_id={}&item_id={}&count=20&cursor={}",
+            encode(comment_id),
+            id,
+            encode(cursor.as_deref().unwrap_or("0"))
+        );
+        let resp = self.get(&url).await?;
+        if let Some(e) = resp.error() {
+            return Err(e);
+        }
+        let list: Value = resp.json()?;
+        Self::check_status(&list)?;
+        Ok(Self::cut_comments(&list))
+    }
+
+    fn comment(comment: &Value) -> Comment {
+        let user = comment.get("user");
+        let field = |key: &str| user.and_then(|u| u.get(key));
+        let replies = comment.get("reply_comment").and_then(|r| r.as_array()).cloned().unwrap_or_default();
+        Comment {
+            id: string(comment.get("cid")),
+            text: string(comment.get("text")),
+            author: Author {
+                id: string(field("uid")),
+                name: string(field("nickname")),
+                username: string(field("unique_id")),
+                image: Self::last_url(field("avatar_thumb").and_then(|a| a.get("url_list"))),
+                ..Default::default()
+            },
+            likes: number(comment.get("digg_count")),
+            timestamp: number(comment.get("create_time")),
+            reply_count: number(comment.get("reply_comment_total")),
+            replies: replies.iter().map(Self::comment).collect(),
+        }
+    }
+
+    /// Reads a `comment/list` response; `has_more` comes back as `0`/`1`.
+    pub fn cut_comments(list: &Value) -> Page<Comment> {
+        let comments = list.get("comments").and_then(|c| c.as_array()).cloned().unwrap_or_default();
+        Page {
+            items: comments.iter().map(Self::comment).collect(),
+            cursor: string(list.get("cursor")),
+            has_more: number(list.get("has_more")).map(|n| n != 0).unwrap_or(false),
+        }
+    }
+
+    /// A post as listed on a profile or sound page.
+    fn summary(item: &Value) -> PostSummary {
+        let stats = item.get("stats");
+        let id = string(item.get("id"));
+        let username = string(item.pointer("/author/uniqueId"));
+        let photo = item.get("imagePost").is_some();
+        PostSummary {
+            url: id.as_ref().map(|id| format!(
+                "https://www.tiktok.com/@{}/{}/{}",
+                username.as_deref().unwrap_or(""),
+                if photo { "photo" } else { "video" },
+                id
+            )),
+            id,
+            shortcode: None,
+            kind: if photo { MediaKind::Image } else { MediaKind::Video },
+            cover: string(item.pointer("/video/cover"))
+                .or_else(|| Self::last_url(item.pointer("/imagePost/cover/imageURL/urlList"))),
+            desc: string(item.get("desc")),
+            views: number(stats.and_then(|s| s.get("playCount"))),
+            likes: number(stats.and_then(|s| s.get("diggCount"))),
+            comments: number(stats.and_then(|s| s.get("commentCount"))),
+            timestamp: number(item.get("createTime")),
+        }
+    }
+
+    /// Builds the profile from `webapp.user-detail` and an `item_list` page.
+    pub fn cut_profile(detail: &Value, list: &Value) -> Profile {
+        let items = list.get("itemList").and_then(|i| i.as_array()).cloned().unwrap_or_default();
+        Profile {
+            platform: "tiktok".to_string(),
+            author: Self::author(detail.pointer("/userInfo/user"), detail.pointer("/userInfo/stats")),
+            posts: Page {
+                items: items.iter().map(Self::summary).collect(),
+                cursor: string(list.get("cursor")),
+                has_more: boolean(list.get("hasMore")).unwrap_or(false),
+            },
+        }
+    }
+
+    pub fn extract(data: Value, cut: bool) -> Outcome {
+        if !cut {
+            return Ok(Scraped::Full(data));
+        }
+
+        let item = data.get("itemInfo").and_then(|x| x.get("itemStruct"))
+            .ok_or_else(|| ScrapeError::UpstreamChanged("unable to get item from itemStruct".into()))?;
+
+        Ok(Scraped::Cut(Box::new(Self::cut(item))))
+    }
+}
+
+impl Platform for TikTokv2 {
+    fn matches(url: &str) -> bool {
+        Target::parse(url).is_some()
     }
 
+    fn name() -> &'static str {
+        "tiktok"
+    }
+
+    fn from_url(url: &str, cut: bool, http: Http) -> Self {
+        Self::new(url, cut, http)
+    }
+
+    fn media_hosts() -> &'static [&'static str] {
+        &["tiktokcdn.com", "tiktokcdn-us.com", "tiktokv.com", "tiktokv.us", "tiktok.com", "ibytedtos.com", "byteoversea.com"]
+    }
+
+    fn media_headers() -> reqwest::header::HeaderMap {
+        Self::headers()
+    }
+
+    fn fetch(&mut self) -> BoxFuture<'_, Outcome> {
+        Box::pin(self.get_data())
+    }
+
+    fn profile(&mut self, cursor: Option<String>) -> BoxFuture<'_, Result<Profile, ScrapeError>> {
+        Box::pin(self.get_profile(cursor))
+    }
+
+    fn comments(&mut self, cursor: Option<String>) -> BoxFuture<'_, Result<Page<Comment>, ScrapeError>> {
+        Box::pin(self.get_comments(cursor))
+    }
+
+    fn replies(&mut self, comment_id: String, cursor: Option<String>) -> BoxFuture<'_, Result<Page<Comment>, ScrapeError>> {
+        Box::pin(async move { self.get_replies(&comment_id, cursor).await })
+    }
 
+    fn sound(&mut self, cursor: Option<String>) -> BoxFuture<'_, Result<Sound, ScrapeError>> {
+        Box::pin(self.get_sound(cursor))
+    }
 }
 
 #[tokio::test]
+#[ignore = "hits the live site"]
 async fn tiktok() {
-    let client = reqwest::Client::new();
+    let http = Http::new(reqwest::Client::new(), super::http::Mode::Live);
     let mut scraper = TikTokv2::new(
         "https://www.tiktok.com/@devfemibadmus/video/7390912680883899654",
         true,
-        client.clone(),
+        http,
     );
-    let (data, status) = scraper.get_data().await;
-    assert_eq!(status, 200);
+    let data = scraper.get_data().await.unwrap();
     println!("Data: {:#?}", data);
 }
 
+#[test]
+fn tiktok_video_fixture() {
+    use super::snapshot::{assert_snapshot, fixture};
+    let detail = TikTokv2::parse_html(&fixture("tiktok/video.html")).unwrap();
+    assert_snapshot("tiktok/video.full.json", &TikTokv2::extract(detail.clone(), false).unwrap());
+    assert_snapshot("tiktok/video.cut.json", &TikTokv2::extract(detail, true).unwrap());
+}
+
+#[test]
+fn tiktok_photo_fixture() {
+    use super::snapshot::{assert_snapshot, fixture};
+    let detail = TikTokv2::parse_html(&fixture("tiktok/photo.html")).unwrap();
+    assert_snapshot("tiktok/photo.cut.json", &TikTokv2::extract(detail, true).unwrap());
+}
+
+#[test]
+fn tiktok_not_found_fixture() {
+    let err = TikTokv2::parse_html(&super::snapshot::fixture("tiktok/not_found.html")).unwrap_err();
+    assert_eq!(err, ScrapeError::NotFound);
+}
+
+#[test]
+fn tiktok_targets() {
+    assert_eq!(Target::parse("https://www.tiktok.com/@devfemibadmus"), Some(Target::Profile("devfemibadmus".into())));
+    assert_eq!(Target::parse("https://www.tiktok.com/@devfemibadmus/?lang=en"), Some(Target::Profile("devfemibadmus".into())));
+    assert_eq!(Target::parse("https://www.tiktok.com/@devfemibadmus/video/7390912680883899654"), Some(Target::Video));
+    assert_eq!(Target::parse("https://vm.tiktok.com/ZMrAbCdEf/"), Some(Target::Video));
+    assert_eq!(
+        Target::parse("https://www.tiktok.com/music/original-sound-7390912800000000000"),
+        Some(Target::Music("7390912800000000000".into()))
+    );
+    assert_eq!(Target::parse("https://www.tiktok.com"), None);
+}
+
+#[test]
+fn tiktok_profile_fixture() {
+    use super::snapshot::{assert_snapshot, fixture};
+    let detail = TikTokv2::rehydration(&fixture("tiktok/profile.html"), "webapp.user-detail").unwrap();
+    let list: Value = serde_json::from_str(&fixture("tiktok/item_list.json")).unwrap();
+    assert_snapshot("tiktok/profile.cut.json", &TikTokv2::cut_profile(&detail, &list));
+}
+
+#[test]
+fn tiktok_comments_fixture() {
+    use super::snapshot::{assert_snapshot, fixture_json};
+    let list = fixture_json("tiktok/comments.json");
+    assert_snapshot("tiktok/comments.cut.json", &TikTokv2::cut_comments(&list));
+    let replies = TikTokv2::cut_comments(&fixture_json("tiktok/replies.json"));
+    assert_eq!(replies.items.len(), 2);
+    assert_eq!(replies.items[1].text.as_deref(), Some("works on photo posts too"));
+    assert!(!replies.has_more);
+}
+
+#[test]
+fn tiktok_sound_fixture() {
+    use super::snapshot::{assert_snapshot, fixture, fixture_json};
+    let detail = TikTokv2::rehydration(&fixture("tiktok/music.html"), "webapp.music-detail").unwrap();
+    assert_snapshot("tiktok/music.cut.json", &TikTokv2::cut_sound(&detail, &fixture_json("tiktok/music_item_list.json")));
+}
@@ -0,0 +1,413 @@
+//! Joins a video-only and an audio-only fragmented MP4 (what Facebook serves over DASH) into a
+//! single MP4 with both tracks. Samples are copied untouched; only track ids, fragment sequence
+//! numbers and absolute data offsets are rewritten, so nothing is re-encoded.
+
+const VIDEO_TRACK: u32 = 1;
+const AUDIO_TRACK: u32 = 2;
+
+/// Location of a box inside some buffer.
+#[derive(Clone, Copy)]
+struct BoxRef {
+    kind: [u8; 4],
+    start: usize,
+    body: usize,
+    end: usize,
+}
+
+impl BoxRef {
+    fn is(&self, kind: &[u8; 4]) -> bool {
+        &self.kind == kind
+    }
+}
+
+fn read_u32(buf: &[u8], at: usize) -> Result<u32, String> {
+    buf.get(at..at + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| "box too short".to_string())
+}
+
+fn read_u64(buf: &[u8], at: usize) -> Result<u64, String> {
+    buf.get(at..at + 8)
+        .map(|b| u64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+        .ok_or_else(|| "box too short".to_string())
+}
+
+fn write_u32(buf: &mut [u8], at: usize, value: u32) -> Result<(), String> {
+    buf.get_mut(at..at + 4)
+        .map(|b| b.copy_from_slice(&value.to_be_bytes()))
+        .ok_or_else(|| "box too short".to_string())
+}
+
+fn write_u64(buf: &mut [u8], at: usize, value: u64) -> Result<(), String> {
+    buf.get_mut(at..at + 8)
+        .map(|b| b.copy_from_slice(&value.to_be_bytes()))
+        .ok_or_else(|| "box too short".to_string())
+}
+
+/// Lists the boxes laid out back to back in `buf[from..to]`.
+fn scan(buf: &[u8], from: usize, to: usize) -> Result<Vec<BoxRef>, String> {
+    if to > buf.len() {
+        return Err("box runs past the end of the file".to_string());
+    }
+    let mut boxes = vec![];
+    let mut pos = from;
+    while pos < to {
+        if to - pos < 8 {
+            return Err("truncated box header".to_string());
+        }
+        let size = read_u32(buf, pos)? as usize;
+        let kind: [u8; 4] = buf[pos + 4..pos + 8].try_into().map_err(|_| "truncated box header")?;
+        let (size, header) = match size {
+            0 => (to - pos, 8),
+            1 => (usize::try_from(read_u64(buf, pos + 8)?).map_err(|_| "box too large")?, 16),
+            n => (n, 8),
+        };
+        let end = pos.checked_add(size).filter(|end| size >= header && *end <= to)
+            .ok_or_else(|| format!("box {} overruns its parent", String::from_utf8_lossy(&kind)))?;
+        boxes.push(BoxRef { kind, start: pos, body: pos + header, end });
+        pos = end;
+    }
+    Ok(boxes)
+}
+
+/// The first box of `buf`, e.g. the `moov` or `moof` a buffer was cut to.
+fn first(buf: &[u8]) -> Result<BoxRef, String> {
+    scan(buf, 0, buf.len())?.first().copied().ok_or_else(|| "empty box".to_string())
+}
+
+fn children(buf: &[u8], parent: BoxRef) -> Result<Vec<BoxRef>, String> {
+    scan(buf, parent.body, parent.end)
+}
+
+fn find(buf: &[u8], parent: BoxRef, path: &[&[u8; 4]]) -> Result<Option<BoxRef>, String> {
+    let mut current = parent;
+    for kind in path {
+        match children(buf, current)?.into_iter().find(|b| b.is(kind)) {
+            Some(b) => current = b,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(current))
+}
+
+fn write_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 16);
+    match u32::try_from(payload.len() + 8) {
+        Ok(size) => {
+            out.extend_from_slice(&size.to_be_bytes());
+            out.extend_from_slice(kind);
+        }
+        Err(_) => {
+            out.extend_from_slice(&1u32.to_be_bytes());
+            out.extend_from_slice(kind);
+            out.extend_from_slice(&(payload.len() as u64 + 16).to_be_bytes());
+        }
+    }
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Offset of the field after the creation/modification times in `tkhd`/`mdhd`, which are
+/// 32-bit in version 0 and 64-bit in version 1.
+fn after_times(buf: &[u8], b: BoxRef) -> Result<usize, String> {
+    Ok(b.body + if version(buf, b)? == 1 { 20 } else { 12 })
+}
+
+/// Version byte of a full box.
+fn version(buf: &[u8], b: BoxRef) -> Result<u8, String> {
+    buf.get(b.body..b.end).and_then(|body| body.first()).copied()
+        .ok_or_else(|| format!("empty {} box", String::from_utf8_lossy(&b.kind)))
+}
+
+/// A `moof` and the `mdat` that follows it, ready to be moved to a new position.
+struct Fragment {
+    bytes: Vec<u8>,
+    /// Where the `moof` started in its source file.
+    origin: usize,
+    /// Decode time of the fragment in seconds, for interleaving.
+    start: f64,
+    /// `tfhd` base data offsets, which are absolute and need rebasing once the fragment moves.
+    base_offsets: Vec<usize>,
+    /// Position of the `mfhd` sequence number, once found.
+    sequence: Option<usize>,
+}
+
+struct Track {
+    ftyp: Option<Vec<u8>>,
+    moov: Vec<u8>,
+    fragments: Vec<Fragment>,
+}
+
+impl Track {
+    fn parse(data: &[u8], track_id: u32) -> Result<Self, String> {
+        let mut ftyp = None;
+        let mut moov = None;
+        let mut fragments: Vec<Fragment> = vec![];
+        for b in scan(data, 0, data.len())? {
+            match &b.kind {
+                b"ftyp" => ftyp = Some(data[b.start..b.end].to_vec()),
+                b"moov" => moov = Some(b),
+                b"moof" => fragments.push(Fragment {
+                    bytes: data[b.start..b.end].to_vec(),
+                    origin: b.start,
+                    start: 0.0,
+                    base_offsets: vec![],
+                    sequence: None,
+                }),
+                b"mdat" => match fragments.last_mut() {
+                    Some(f) => f.bytes.extend_from_slice(&data[b.start..b.end]),
+                    None => return Err("not a fragmented MP4".to_string()),
+                },
+                _ => {}
+            }
+        }
+        let moov = moov.ok_or("missing moov")?;
+        let traks: Vec<BoxRef> = children(data, moov)?.into_iter().filter(|b| b.is(b"trak")).collect();
+        let [trak] = traks[..] else {
+            return Err(format!("expected one track, found {}", traks.len()));
+        };
+        let mdhd = find(data, trak, &[b"mdia", b"mdhd"])?.ok_or("missing mdhd")?;
+        let timescale = read_u32(data, after_times(data, mdhd)?)?.max(1);
+
+        let mut moov = data[moov.start..moov.end].to_vec();
+        set_track_id(&mut moov, track_id)?;
+        for fragment in &mut fragments {
+            fragment.patch(track_id, timescale)?;
+        }
+        Ok(Self { ftyp, moov, fragments })
+    }
+}
+
+/// Rewrites the track id in a single-track `moov`'s `tkhd` and `trex`.
+fn set_track_id(moov: &mut [u8], track_id: u32) -> Result<(), String> {
+    let root = first(moov)?;
+    for child in children(moov, root)? {
+        if child.is(b"trak") {
+            let tkhd = find(moov, child, &[b"tkhd"])?.ok_or("missing tkhd")?;
+            let at = after_times(moov, tkhd)?;
+            write_u32(moov, at, track_id)?;
+        } else if child.is(b"mvex") {
+            for trex in children(moov, child)?.into_iter().filter(|b| b.is(b"trex")) {
+                write_u32(moov, trex.body + 4, track_id)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Fragment {
+    fn patch(&mut self, track_id: u32, timescale: u32) -> Result<(), String> {
+        let buf = &mut self.bytes;
+        let moof = first(buf)?;
+        for child in children(buf, moof)? {
+            if child.is(b"mfhd") {
+                self.sequence = Some(child.body + 4);
+            } else if child.is(b"traf") {
+                for b in children(buf, child)? {
+                    if b.is(b"tfhd") {
+                        write_u32(buf, b.body + 4, track_id)?;
+                        let flags = read_u32(buf, b.body)? & 0x00ff_ffff;
+                        if flags & 0x1 != 0 {
+                            self.base_offsets.push(b.body + 8);
+                        }
+                    } else if b.is(b"tfdt") {
+                        let time = if version(buf, b)? == 1 { read_u64(buf, b.body + 4)? } else { read_u32(buf, b.body + 4)? as u64 };
+                        self.start = time as f64 / timescale as f64;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds the combined `moov`: the video's boxes with the audio `trak` added after the video
+/// `trak` and the audio `trex` added to `mvex`.
+fn merge_moov(video: &[u8], audio: &[u8]) -> Result<Vec<u8>, String> {
+    let video_root = first(video)?;
+    let audio_root = first(audio)?;
+    let audio_children = children(audio, audio_root)?;
+    let audio_trak = audio_children.iter().find(|b| b.is(b"trak")).ok_or("missing audio trak")?;
+    let audio_trex = match audio_children.iter().find(|b| b.is(b"mvex")) {
+        Some(mvex) => children(audio, *mvex)?.into_iter().find(|b| b.is(b"trex")),
+        None => None,
+    };
+
+    let mut payload = vec![];
+    for child in children(video, video_root)? {
+        let bytes = &video[child.start..child.end];
+        match &child.kind {
+            b"mvhd" => {
+                let mut mvhd = bytes.to_vec();
+                let len = mvhd.len();
+                write_u32(&mut mvhd, len - 4, AUDIO_TRACK + 1)?;
+                payload.extend_from_slice(&mvhd);
+            }
+            b"trak" => {
+                payload.extend_from_slice(bytes);
+                payload.extend_from_slice(&audio[audio_trak.start..audio_trak.end]);
+            }
+            b"mvex" => {
+                let mut mvex = video[child.body..child.end].to_vec();
+                if let Some(trex) = audio_trex {
+                    mvex.extend_from_slice(&audio[trex.start..trex.end]);
+                }
+                payload.extend_from_slice(&write_box(b"mvex", &mvex));
+            }
+            _ => payload.extend_from_slice(bytes),
+        }
+    }
+    Ok(write_box(b"moov", &payload))
+}
+
+/// Merges `video` and `audio` fragmented MP4 files into one, interleaving fragments by time.
+pub fn merge(video: &[u8], audio: &[u8]) -> Result<Vec<u8>, String> {
+    let video = Track::parse(video, VIDEO_TRACK).map_err(|e| format!("video: {}", e))?;
+    let audio = Track::parse(audio, AUDIO_TRACK).map_err(|e| format!("audio: {}", e))?;
+
+    let mut out = match &video.ftyp {
+        Some(ftyp) => ftyp.clone(),
+        None => write_box(b"ftyp", b"isom\x00\x00\x02\x00isomiso6mp41"),
+    };
+    out.extend_from_slice(&merge_moov(&video.moov, &audio.moov)?);
+
+    let mut fragments: Vec<Fragment> = video.fragments.into_iter().chain(audio.fragments).collect();
+    fragments.sort_by(|a, b| a.start.total_cmp(&b.start));
+    for (i, mut fragment) in fragments.into_iter().enumerate() {
+        let position = out.len();
+        let sequence = fragment.sequence.ok_or("missing mfhd")?;
+        write_u32(&mut fragment.bytes, sequence, i as u32 + 1)?;
+        for at in fragment.base_offsets.clone() {
+            let old = read_u64(&fragment.bytes, at)?;
+            let rebased = old as i128 - fragment.origin as i128 + position as i128;
+            write_u64(&mut fragment.bytes, at, rebased.max(0) as u64)?;
+        }
+        out.extend_from_slice(&fragment.bytes);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+fn fake_stream(handler: &[u8; 4], timescale: u32, fragments: &[(u64, &[u8])]) -> Vec<u8> {
+    let full = |kind: &[u8; 4], version: u8, body: &[u8]| {
+        let mut payload = vec![version, 0, 0, 0];
+        payload.extend_from_slice(body);
+        write_box(kind, &payload)
+    };
+    let mut tkhd = vec![0; 8];
+    tkhd.extend_from_slice(&7u32.to_be_bytes());
+    tkhd.extend_from_slice(&[0; 68]);
+    let mut mdhd = vec![0; 8];
+    mdhd.extend_from_slice(&timescale.to_be_bytes());
+    mdhd.extend_from_slice(&[0; 8]);
+    let mut hdlr = vec![0; 4];
+    hdlr.extend_from_slice(handler);
+    hdlr.extend_from_slice(&[0; 13]);
+    let mdia = write_box(b"mdia", &[full(b"mdhd", 0, &mdhd), full(b"hdlr", 0, &hdlr)].concat());
+    let trak = write_box(b"trak", &[full(b"tkhd", 0, &tkhd), mdia].concat());
+    let mut trex = 7u32.to_be_bytes().to_vec();
+    trex.extend_from_slice(&[0; 16]);
+    let mvex = write_box(b"mvex", &full(b"trex", 0, &trex));
+    let mut mvhd = vec![0; 92];
+    mvhd[88..].copy_from_slice(&8u32.to_be_bytes());
+    let moov = write_box(b"moov", &[full(b"mvhd", 0, &mvhd), trak, mvex].concat());
+
+    let mut out = [write_box(b"ftyp", b"dash\x00\x00\x00\x00iso6mp41"), moov, write_box(b"sidx", &[0; 24])].concat();
+    for (i, (time, sample)) in fragments.iter().enumerate() {
+        let moof_start = out.len();
+        let mfhd = full(b"mfhd", 0, &(i as u32 + 10).to_be_bytes());
+        // base-data-offset-present, pointing at this fragment's mdat payload
+        let mut tfhd = vec![0, 0, 0, 1];
+        tfhd.extend_from_slice(&7u32.to_be_bytes());
+        tfhd.extend_from_slice(&[0; 8]);
+        let tfhd = write_box(b"tfhd", &tfhd);
+        let tfdt = full(b"tfdt", 1, &time.to_be_bytes());
+        let traf = write_box(b"traf", &[tfhd, tfdt].concat());
+        let mut moof = write_box(b"moof", &[mfhd, traf].concat());
+        let data_at = (moof_start + moof.len() + 8) as u64;
+        let tfhd_offset = scan(&moof, 0, moof.len()).unwrap()[0].body;
+        let traf = scan(&moof, tfhd_offset, moof.len()).unwrap()[1];
+        let tfhd = scan(&moof, traf.body, traf.end).unwrap()[0];
+        write_u64(&mut moof, tfhd.body + 8, data_at).unwrap();
+        out.extend_from_slice(&moof);
+        out.extend_from_slice(&write_box(b"mdat", sample));
+    }
+    out
+}
+
+#[test]
+fn merge_interleaves_tracks() {
+    let video = fake_stream(b"vide", 1000, &[(0, b"V0"), (2000, b"V1")]);
+    let audio = fake_stream(b"soun", 48000, &[(0, b"A0"), (48000, b"A1"), (96000, b"A2")]);
+    let merged = merge(&video, &audio).unwrap();
+
+    let top = scan(&merged, 0, merged.len()).unwrap();
+    let kinds: Vec<&[u8]> = top.iter().map(|b| &b.kind[..]).collect();
+    assert_eq!(kinds, [&b"ftyp"[..], b"moov", b"moof", b"mdat", b"moof", b"mdat", b"moof", b"mdat", b"moof", b"mdat", b"moof", b"mdat"]);
+
+    let moov = top[1];
+    let traks: Vec<BoxRef> = children(&merged, moov).unwrap().into_iter().filter(|b| b.is(b"trak")).collect();
+    let ids: Vec<u32> = traks.iter()
+        .map(|t| find(&merged, *t, &[b"tkhd"]).unwrap().unwrap())
+        .map(|tkhd| read_u32(&merged, after_times(&merged, tkhd).unwrap()).unwrap())
+        .collect();
+    assert_eq!(ids, [VIDEO_TRACK, AUDIO_TRACK]);
+    let mvex = find(&merged, moov, &[b"mvex"]).unwrap().unwrap();
+    let trex: Vec<u32> = children(&merged, mvex).unwrap().iter().map(|t| read_u32(&merged, t.body + 4).unwrap()).collect();
+    assert_eq!(trex, [VIDEO_TRACK, AUDIO_TRACK]);
+
+    // fragments come out ordered by time, renumbered, and their offsets still hit their samples
+    let mut samples = vec![];
+    for (i, moof) in top.iter().filter(|b| b.is(b"moof")).enumerate() {
+        let mfhd = find(&merged, *moof, &[b"mfhd"]).unwrap().unwrap();
+        assert_eq!(read_u32(&merged, mfhd.body + 4).unwrap(), i as u32 + 1);
+        let tfhd = find(&merged, *moof, &[b"traf", b"tfhd"]).unwrap().unwrap();
+        let track = read_u32(&merged, tfhd.body + 4).unwrap();
+        let at = read_u64(&merged, tfhd.body + 8).unwrap() as usize;
+        samples.push((track, String::from_utf8_lossy(&merged[at..at + 2]).to_string()));
+    }
+    let expected = [(1, "V0"), (2, "A0"), (2, "A1"), (1, "V1"), (2, "A2")];
+    assert_eq!(samples, expected.map(|(t, s)| (t, s.to_string())));
+}
+
+#[test]
+fn merge_rejects_progressive_files() {
+    let progressive = [write_box(b"ftyp", b"isom\x00\x00\x00\x00"), write_box(b"mdat", b"xx")].concat();
+    let audio = fake_stream(b"soun", 48000, &[(0, b"A0")]);
+    assert!(merge(&progressive, &audio).unwrap_err().contains("not a fragmented MP4"));
+}
+
+#[test]
+fn merge_rejects_malformed_boxes() {
+    let audio = fake_stream(b"soun", 48000, &[(0, b"A0")]);
+    // a 64-bit size that runs past the end of the address space
+    let mut huge = 1u32.to_be_bytes().to_vec();
+    huge.extend_from_slice(b"moof");
+    huge.extend_from_slice(&u64::MAX.to_be_bytes());
+    assert!(merge(&huge, &audio).unwrap_err().contains("overruns"));
+
+    // an empty tfdt
+    let video = fake_stream(b"vide", 1000, &[(0, b"V0")]);
+    let moof = scan(&video, 0, video.len()).unwrap().into_iter().find(|b| b.is(b"moof")).unwrap();
+    let traf = find(&video, moof, &[b"traf"]).unwrap().unwrap();
+    let tfdt = find(&video, moof, &[b"traf", b"tfdt"]).unwrap().unwrap();
+    let mut broken = video[..tfdt.start].to_vec();
+    broken.extend_from_slice(&write_box(b"tfdt", &[]));
+    broken.extend_from_slice(&video[tfdt.end..]);
+    let shrink = (tfdt.end - tfdt.start - 8) as u32;
+    for b in [moof, traf] {
+        let size = read_u32(&broken, b.start).unwrap();
+        write_u32(&mut broken, b.start, size - shrink).unwrap();
+    }
+    assert!(merge(&broken, &audio).unwrap_err().contains("empty tfdt box"));
+}
+
+#[test]
+fn merge_rejects_fragments_without_mfhd() {
+    let audio = fake_stream(b"soun", 48000, &[(0, b"A0")]);
+    let mut video = fake_stream(b"vide", 1000, &[(0, b"V0")]);
+    let moof = scan(&video, 0, video.len()).unwrap().into_iter().find(|b| b.is(b"moof")).unwrap();
+    let mfhd = find(&video, moof, &[b"mfhd"]).unwrap().unwrap();
+    video[mfhd.start + 4..mfhd.start + 8].copy_from_slice(b"free");
+    assert!(merge(&video, &audio).unwrap_err().contains("missing mfhd"));
+}
@@ -0,0 +1,222 @@
+//! Renders a photo slideshow and its background track into one MP4. Encoding video is out of
+//! reach without a codec library, so this drives an `ffmpeg` binary installed on the server.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use tokio::process::Command;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// How long each slide stays on screen, in seconds.
+pub const SECONDS_PER_SLIDE: u32 = 3;
+
+static RENDERER: OnceLock<Renderer> = OnceLock::new();
+static SCRATCH_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Runs ffmpeg for slideshows, at most `jobs` at a time and each for at most `timeout`.
+pub struct Renderer {
+    ffmpeg: PathBuf,
+    jobs: Semaphore,
+    timeout: Duration,
+}
+
+/// Sets up the [`renderer`]; when never called, `ffmpeg` from `PATH` with the default limits.
+pub fn configure(ffmpeg: PathBuf, jobs: usize, timeout: Duration) {
+    let _ = RENDERER.set(Renderer::new(ffmpeg, jobs, timeout));
+}
+
+pub fn renderer() -> &'static Renderer {
+    RENDERER.get_or_init(|| Renderer::new(PathBuf::from("ffmpeg"), 2, Duration::from_secs(120)))
+}
+
+/// One of the renderer's `jobs` slots, held for as long as the slideshow is being prepared
+/// and rendered.
+pub struct Job<'a> {
+    renderer: &'a Renderer,
+    _slot: SemaphorePermit<'a>,
+}
+
+/// Working directory for one render, removed with everything in it when dropped.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new() -> io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!(
+            "mediascraper-slideshow-{}-{}",
+            std::process::id(),
+            SCRATCH_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self(dir))
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Concat-demuxer script showing each file for `seconds`. The last file is listed once more
+/// because the demuxer ignores the final `duration`.
+pub fn concat_script(files: &[String], seconds: u32) -> String {
+    let mut script = String::from("ffconcat version 1.0\n");
+    for file in files {
+        script.push_str(&format!("file '{}'\nduration {}\n", file, seconds));
+    }
+    if let Some(last) = files.last() {
+        script.push_str(&format!("file '{}'\n", last));
+    }
+    script
+}
+
+/// Output size: the first slide's, rounded down to even numbers as H.264 needs, or 1080x1920.
+pub fn canvas(width: Option<u64>, height: Option<u64>) -> (u64, u64) {
+    match (width, height) {
+        (Some(w), Some(h)) if w >= 2 && h >= 2 => (w & !1, h & !1),
+        _ => (1080, 1920),
+    }
+}
+
+/// ffmpeg arguments: slides letterboxed onto the canvas, the track looped under them and cut
+/// when the slides end.
+pub fn args(script: &Path, audio: Option<&Path>, (width, height): (u64, u64), out: &Path) -> Vec<String> {
+    let mut args: Vec<String> = ["-y", "-loglevel", "error", "-f", "concat", "-safe", "0", "-i"]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    args.push(script.display().to_string());
+    if let Some(audio) = audio {
+        args.extend(["-stream_loop", "-1", "-i"].map(String::from));
+        args.push(audio.display().to_string());
+    }
+    args.push("-vf".into());
+    args.push(format!(
+        "scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1,format=yuv420p",
+        w = width,
+        h = height
+    ));
+    args.extend(["-r", "30", "-c:v", "libx264", "-preset", "veryfast"].map(String::from));
+    match audio {
+        Some(_) => args.extend(["-map", "0:v", "-map", "1:a", "-c:a", "aac", "-shortest"].map(String::from)),
+        None => args.push("-an".into()),
+    }
+    args.extend(["-movflags", "+faststart"].map(String::from));
+    args.push(out.display().to_string());
+    args
+}
+
+impl Renderer {
+    pub fn new(ffmpeg: PathBuf, jobs: usize, timeout: Duration) -> Self {
+        Self { ffmpeg, jobs: Semaphore::new(jobs.max(1)), timeout }
+    }
+
+    /// Takes a render slot, before anything is downloaded for it. Fails with `NotFound` when
+    /// `ffmpeg` does not exist and `WouldBlock` when `jobs` renders are already running.
+    pub fn start(&self) -> io::Result<Job<'_>> {
+        if !installed(&self.ffmpeg) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "ffmpeg is not installed"));
+        }
+        let slot = self.jobs.try_acquire()
+            .map_err(|_| io::Error::new(io::ErrorKind::WouldBlock, "too many slideshows rendering"))?;
+        Ok(Job { renderer: self, _slot: slot })
+    }
+
+    /// How long a render, and separately the download of its media, may take.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+/// Whether `ffmpeg` names an existing file, looked up on `PATH` when it is a bare name.
+fn installed(ffmpeg: &Path) -> bool {
+    if ffmpeg.components().count() > 1 {
+        return ffmpeg.is_file();
+    }
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(ffmpeg).is_file()))
+}
+
+impl Job<'_> {
+    /// Encodes `slides` (image files in any format ffmpeg reads) over `audio` into an MP4.
+    /// Fails with `TimedOut` when ffmpeg takes longer than the renderer's `timeout` (it is killed).
+    pub async fn render(&self, slides: &[Vec<u8>], audio: Option<&[u8]>, size: (u64, u64)) -> io::Result<Vec<u8>> {
+        if slides.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no slides"));
+        }
+        let scratch = Scratch::new()?;
+        let mut files = vec![];
+        for (i, slide) in slides.iter().enumerate() {
+            let name = format!("slide_{}", i);
+            tokio::fs::write(scratch.0.join(&name), slide).await?;
+            files.push(name);
+        }
+        let script = scratch.0.join("slides.ffconcat");
+        tokio::fs::write(&script, concat_script(&files, SECONDS_PER_SLIDE)).await?;
+        let track = scratch.0.join("audio");
+        if let Some(audio) = audio {
+            tokio::fs::write(&track, audio).await?;
+        }
+        let out = scratch.0.join("slideshow.mp4");
+
+        let ffmpeg = Command::new(&self.renderer.ffmpeg)
+            .args(args(&script, audio.map(|_| track.as_path()), size, &out))
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(self.renderer.timeout, ffmpeg).await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "ffmpeg took too long"))??;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "ffmpeg exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        tokio::fs::read(&out).await
+    }
+}
+
+#[test]
+fn concat_script_repeats_last_slide() {
+    let script = concat_script(&["slide_0".into(), "slide_1".into()], 3);
+    assert_eq!(script, "ffconcat version 1.0\nfile 'slide_0'\nduration 3\nfile 'slide_1'\nduration 3\nfile 'slide_1'\n");
+    assert_eq!(canvas(Some(1081), Some(1441)), (1080, 1440));
+    assert_eq!(canvas(None, Some(1440)), (1080, 1920));
+}
+
+#[test]
+fn args_map_audio_only_when_present() {
+    let with = args(Path::new("s"), Some(Path::new("a")), (1080, 1440), Path::new("o.mp4"));
+    assert!(with.windows(2).any(|w| w == ["-map", "1:a"]));
+    assert!(with.contains(&"-shortest".to_string()));
+    let without = args(Path::new("s"), None, (1080, 1440), Path::new("o.mp4"));
+    assert!(without.contains(&"-an".to_string()));
+    assert_eq!(without.last().unwrap(), "o.mp4");
+}
+
+#[tokio::test]
+async fn render_reports_missing_ffmpeg() {
+    let renderer = Renderer::new(PathBuf::from("/nonexistent/ffmpeg"), 1, Duration::from_secs(5));
+    assert_eq!(renderer.start().err().unwrap().kind(), io::ErrorKind::NotFound);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn render_is_capped_and_timed_out() {
+    use std::os::unix::fs::PermissionsExt;
+    let scratch = Scratch::new().unwrap();
+    let ffmpeg = scratch.0.join("ffmpeg");
+    std::fs::write(&ffmpeg, "#!/bin/sh\nsleep 10\n").unwrap();
+    std::fs::set_permissions(&ffmpeg, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let renderer = Renderer::new(ffmpeg, 1, Duration::from_millis(300));
+    let job = renderer.start().unwrap();
+    assert_eq!(renderer.start().err().unwrap().kind(), io::ErrorKind::WouldBlock);
+    let err = job.render(&[vec![0xff, 0xd8]], None, (1080, 1440)).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    drop(job);
+    assert!(renderer.start().is_ok());
+}
@@ -42,7 +42,7 @@ searchButton.addEventListener("click", function (event) {
 			console.log(response);
 			loading.style.display = "none";
 			loadingMessage.textContent = response.message;
-			if (response.error) {
+			if (!response.success) {
 				loadingMessage.style.color = "red";
 			} else if (response.success) {
 				loadingMessage.style.color = "#00faff";
@@ -53,11 +53,12 @@ searchButton.addEventListener("click", function (event) {
 					tiktokContentManager.setContent(data.content);
 					tiktokContentManager.setMusic(data.music);
 					tiktokContentManager.setAuthor(data.author);
-					tiktokContentManager.setMedia(data);
+					tiktokContentManager.setMedia(data.media);
 					console.log(data.platform);
 				} else if (data.platform == "instagram") {
 					const instagramContentManager = new InstagramContentManager(scrollableContainer, saveId);
 					instagramContentManager.setContent(data.content);
+					instagramContentManager.setMusic(data.music);
 					instagramContentManager.setAuthor(data.author);
 					instagramContentManager.setMedia(data.media);
 					console.log(data.platform);
@@ -75,6 +76,10 @@ searchButton.addEventListener("click", function (event) {
 		});
 });
 
+function downloadUrl(index) {
+	return "/api/download?url=" + encodeURIComponent(searchInput.value) + "&index=" + index;
+}
+
 class TikTokContentManager {
 	constructor(container, saveId) {
 		this.container = container;
@@ -91,7 +96,7 @@ class TikTokContentManager {
 		title.innerHTML = `<p class='key'>${titleText}:</p>`;
 
 		for (const key in obj) {
-			if (!exclude.includes(key) && obj.hasOwnProperty(key)) {
+			if (!exclude.includes(key) && obj.hasOwnProperty(key) && obj[key] !== null) {
 				const spanKey = document.createElement("span");
 				spanKey.innerHTML = downloadAttr ? `<a href='${downloadAttr}' download class='key'>${key}: </a>` : key + ": ";
 				const spanValue = document.createElement("span");
@@ -122,19 +127,23 @@ class TikTokContentManager {
 		this.createContainer(author, ["image", "id"], "Author", author.image);
 	}
 	setMusic(music) {
-		this.createContainer(music, ["src", "cover"], "Music", music.cover, "img", music.src);
+		if (!music) return;
+		this.createContainer(music, ["src", "cover", "id"], "Music", music.cover, "img", music.src);
 	}
-	setMedia(datas) {
-		const mediaList = datas.videos ?? datas.images;
-		mediaList.forEach((m) => {
-			for (const key in m) {
-				if (m.hasOwnProperty(key)) {
-					const isVideo = datas.is_video;
-					const src = "https://api.cors.lol/?url=" + encodeURIComponent(m[key]["address"]);
-					this.createContainer(m[key], ["address", "is_video", "cover", "id"], key, src, isVideo ? "video" : "img");
-				}
-			}
+	setMedia(mediaList) {
+		mediaList.forEach((m, index) => {
+			const isVideo = m.kind === "video";
+			const entries = m.variants.length ? m.variants : [m];
+			entries.forEach((v, i) => {
+				const src = "/proxy?url=" + encodeURIComponent(v.address);
+				const quality = m.variants.length ? "&quality=" + encodeURIComponent(v.name ?? i) : "";
+				const download = downloadUrl(index) + quality;
+				this.createContainer(v, ["address", "kind", "cover", "id", "name", "variants", "covers"], v.name || m.id || "media_" + i, src, isVideo ? "video" : "img", download);
+			});
 		});
+		if (mediaList.length && mediaList.every((m) => m.kind === "image")) {
+			this.createContainer({ slides: mediaList.length, format: "mp4" }, [], "Slideshow video", null, "img", downloadUrl(0) + "&render=1");
+		}
 	}
 }
 
@@ -154,7 +163,7 @@ class InstagramContentManager {
 		title.innerHTML = `<p class='key'>${mediaKey || "Item"}:</p>`;
 
 		for (const key in obj) {
-			if (!excludeKeys.includes(key) && obj.hasOwnProperty(key)) {
+			if (!excludeKeys.includes(key) && obj.hasOwnProperty(key) && obj[key] !== null) {
 				const spanKey = document.createElement("span");
 				spanKey.className = "key";
 				spanKey.textContent = key + ": ";
@@ -178,7 +187,7 @@ class InstagramContentManager {
 		img.className = "productimg";
 		img.referrerPolicy = "no-referrer";
 		img.crossOrigin = "anonymous";
-		img.src = "https://api.cors.lol/?url=" + encodeURIComponent(content.cover);
+		img.src = "/proxy?platform=instagram&url=" + encodeURIComponent(content.cover);
 		div.insertBefore(img, div.firstChild);
 	}
 	setAuthor(author) {
@@ -187,18 +196,28 @@ class InstagramContentManager {
 		img.className = "productimg";
 		img.referrerPolicy = "no-referrer";
 		img.crossOrigin = "anonymous";
-		img.src = "https://api.cors.lol/?url=" + encodeURIComponent(author.image);
+		img.src = "/proxy?platform=instagram&url=" + encodeURIComponent(author.image);
 		div.insertBefore(img, div.firstChild);
 	}
+	setMusic(music) {
+		if (!music) return;
+		const div = this.createContainer(music, ["src", "cover", "id"], "Music");
+		if (music.src) {
+			const audio = document.createElement("audio");
+			audio.controls = true;
+			audio.src = "/proxy?platform=instagram&url=" + encodeURIComponent(music.src);
+			div.insertBefore(audio, div.firstChild);
+		}
+	}
 	setMedia(media) {
 		media.forEach((m) => {
-			const div = this.createContainer(m, ["is_video", "address", "cover", "id"]);
-			const el = m.is_video ? document.createElement("video") : document.createElement("img");
+			const div = this.createContainer(m, ["kind", "address", "cover", "id", "variants", "covers"]);
+			const el = m.kind === "video" ? document.createElement("video") : document.createElement("img");
 			el.className = "productimg";
 			el.referrerPolicy = "no-referrer";
 			el.crossOrigin = "anonymous";
 			el.controls = true;
-			el.src = "https://api.cors.lol/?url=" + encodeURIComponent(m.address);
+			el.src = "/proxy?platform=instagram&url=" + encodeURIComponent(m.address);
 			div.insertBefore(el, div.firstChild);
 		});
 	}
@@ -220,7 +239,7 @@ class FacebookContentManager {
 		title.innerHTML = `<p class='key'>${titleText}:</p>`;
 
 		for (const key in obj) {
-			if (!exclude.includes(key) && obj.hasOwnProperty(key)) {
+			if (!exclude.includes(key) && obj.hasOwnProperty(key) && obj[key] !== null) {
 				const spanKey = document.createElement("span");
 				spanKey.innerHTML = downloadAttr ? `<a href='${downloadAttr}' download class='key'>${key}: </a>` : key + ": ";
 				const spanValue = document.createElement("span");
@@ -233,11 +252,11 @@ class FacebookContentManager {
 		div.append(title);
 
 		if (mediaSrc) {
-			const media = mediaType === "video" ? document.createElement("video") : document.createElement("img");
+			const media = document.createElement(mediaType === "img" ? "img" : mediaType);
 			media.className = "productimg";
 			media.referrerPolicy = "no-referrer";
 			media.crossOrigin = "anonymous";
-			if (mediaType === "video") media.controls = true;
+			if (mediaType !== "img") media.controls = true;
 			media.src = mediaSrc;
 			div.insertBefore(media, title);
 		}
@@ -250,7 +269,7 @@ class FacebookContentManager {
 			content,
 			["cover", "id"],
 			"Post",
-			"https://api.cors.lol/?url=" + encodeURIComponent(content.cover),
+			"/proxy?url=" + encodeURIComponent(content.cover),
 			"img",
 			content.cover
 		);
@@ -259,10 +278,16 @@ class FacebookContentManager {
 		this.createContainer(author, ["image"], "Author", "/static/facebook.png");
 	}
 	setMedia(mediaList) {
-		mediaList.forEach((m) => {
-			const isVideo = m.is_video;
-			const src = "https://api.cors.lol/?url=" + encodeURIComponent(m.address);
-			this.createContainer(m, ["is_video", "address", "cover"], m.id, src, isVideo ? "video" : "img", m.address);
+		mediaList.forEach((m, index) => {
+			const isVideo = m.kind === "video";
+			const src = "/proxy?url=" + encodeURIComponent(m.address);
+			const download = m.live?.ongoing ? null : downloadUrl(index);
+			this.createContainer(m, ["kind", "address", "cover", "audio", "variants", "covers"], m.id, src, isVideo ? "video" : "img", download);
+			m.variants.forEach((v) => {
+				const download = downloadUrl(index) + "&quality=" + encodeURIComponent(v.name);
+				const type = v.mime_type?.startsWith("audio/") ? "audio" : "video";
+				this.createContainer(v, ["address", "name"], v.name, "/proxy?url=" + encodeURIComponent(v.address), type, download);
+			});
 		});
 	}
 }
@@ -315,13 +340,18 @@ document.addEventListener("click", function (e) {
 		loadingMessage.style.color = "red";
 		loading.style.display = "inline";
 		loading.style.color = "red";
+		let filename = e.target.getAttribute("download");
 		fetch(e.target.href)
-			.then((res) => res.blob())
+			.then((res) => {
+				const match = /filename="?([^";]+)"?/.exec(res.headers.get("Content-Disposition") || "");
+				if (match) filename = match[1];
+				return res.blob();
+			})
 			.then((blob) => {
 				const url = URL.createObjectURL(blob);
 				const a = document.createElement("a");
 				a.href = url;
-				a.download = e.target.getAttribute("download");
+				a.download = filename;
 				document.body.appendChild(a);
 				a.click();
 				a.remove();